@@ -0,0 +1,702 @@
+//! Backends that play, record or drop the sounds that the game triggers.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Seek, SeekFrom, Write},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
+
+use rodio::{Decoder, OutputStreamHandle, Sink, Source};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    machine::{Machine, SoundId},
+    sample_set::Sample,
+    synth, STATES_PER_FRAME,
+};
+
+/// A sound bit of a sound port set or cleared by the game, at `cycle` states since power-on.
+///
+/// A one-shot sound plays to its end once started, whereas a looping sound plays until its bit
+/// is cleared, as `Machine::sounds` tells.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SoundEvent {
+    /// The sound.
+    pub id: SoundId,
+    /// Whether the bit has been set, rather than cleared.
+    pub on: bool,
+    /// The number of states executed since power-on.
+    pub cycle: u64,
+}
+
+/// The volume controls of the listener: a master volume, a mute switch and a gain for each
+/// sound, which multiply the volumes of the samples.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::{audio::Volume, machine::SoundId};
+///
+/// let mut volume = Volume { master: 0.8, ..Volume::default() };
+/// volume.gains.insert(SoundId::UfoLowPitch, 0.5);
+/// assert_eq!(volume.gain(SoundId::UfoLowPitch), 0.4);
+/// assert_eq!(volume.gain(SoundId::Shoot), 0.8);
+/// volume.muted = true;
+/// assert_eq!(volume.gain(SoundId::Shoot), 0.0);
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Volume {
+    /// The gain of all the sounds, 1.0 by default.
+    #[serde(serialize_with = "serialize_gain")]
+    pub master: f32,
+    /// Whether the sounds are silenced, whatever their gains.
+    pub muted: bool,
+    /// The gains of the sounds, which are 1.0 for those left out.
+    #[serde(serialize_with = "serialize_gains")]
+    pub gains: BTreeMap<SoundId, f32>,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume { master: 1.0, muted: false, gains: BTreeMap::new() }
+    }
+}
+
+impl Volume {
+    /// Returns the gain that `sound` is played with, 0.0 while the sounds are muted.
+    pub fn gain(&self, sound: SoundId) -> f32 {
+        match self.muted {
+            true => 0.0,
+            false => self.master * self.gains.get(&sound).copied().unwrap_or(1.0),
+        }
+    }
+}
+
+/// Returns the `f64` that `gain` reads as, so that a gain of 0.4 is serialized as 0.4 rather
+/// than as 0.4000000059604645.
+fn decimal(gain: f32) -> f64 {
+    gain.to_string().parse().unwrap_or(f64::from(gain))
+}
+
+fn serialize_gain<S: Serializer>(gain: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(decimal(*gain))
+}
+
+fn serialize_gains<S: Serializer>(
+    gains: &BTreeMap<SoundId, f32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(gains.iter().map(|(sound, &gain)| (sound, decimal(gain))))
+}
+
+/// A backend that receives the sounds that the machine triggers.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::{
+///     audio::{RecordingBackend, SoundEvent},
+///     machine::SoundId,
+///     SpaceInvaders,
+/// };
+///
+/// // MVI A,$02; OUT 3; JMP $0004
+/// let mut invaders_h = vec![0; 0x800];
+/// invaders_h[..7].copy_from_slice(&[0x3E, 0x02, 0xD3, 0x03, 0xC3, 0x04, 0x00]);
+/// let recorder = RecordingBackend::new();
+/// let mut space_invaders = SpaceInvaders::builder()
+///     .rom_bytes(invaders_h)
+///     .rom_bytes(vec![0; 0x800])
+///     .rom_bytes(vec![0; 0x800])
+///     .rom_bytes(vec![0; 0x800])
+///     .audio_backend(recorder.clone())
+///     .build()?;
+/// space_invaders.run_frame()?;
+/// assert_eq!(recorder.events(), [SoundEvent { id: SoundId::Shoot, on: true, cycle: 17 }]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait AudioBackend: Send {
+    /// Handles a sound bit set or cleared by the game.
+    fn sound_event(&mut self, event: SoundEvent);
+
+    /// Handles the amplifier being enabled or disabled at `cycle`. The sounds are muted while it
+    /// is disabled, as it is at power-on.
+    fn amplifier_event(&mut self, enabled: bool, cycle: u64);
+
+    /// Cuts short the one-shot sounds being played, as the machine jumps to another point in time
+    /// at `cycle` by loading a state or being reset.
+    fn stop_one_shot_sounds(&mut self, cycle: u64);
+
+    /// Handles the end of a video frame at `cycle`. Does nothing by default.
+    fn end_frame(&mut self, _cycle: u64) {}
+
+    /// Applies the volume controls to the sounds, including those being played. Does nothing by
+    /// default.
+    fn set_volume(&mut self, _volume: &Volume) {}
+}
+
+impl<B: AudioBackend + ?Sized> AudioBackend for Box<B> {
+    fn sound_event(&mut self, event: SoundEvent) {
+        (**self).sound_event(event);
+    }
+
+    fn amplifier_event(&mut self, enabled: bool, cycle: u64) {
+        (**self).amplifier_event(enabled, cycle);
+    }
+
+    fn stop_one_shot_sounds(&mut self, cycle: u64) {
+        (**self).stop_one_shot_sounds(cycle);
+    }
+
+    fn end_frame(&mut self, cycle: u64) {
+        (**self).end_frame(cycle);
+    }
+
+    fn set_volume(&mut self, volume: &Volume) {
+        (**self).set_volume(volume);
+    }
+}
+
+/// A pair of backends, both of which receive every event, such as one that plays the sounds and
+/// one that records them.
+impl<A: AudioBackend, B: AudioBackend> AudioBackend for (A, B) {
+    fn sound_event(&mut self, event: SoundEvent) {
+        self.0.sound_event(event);
+        self.1.sound_event(event);
+    }
+
+    fn amplifier_event(&mut self, enabled: bool, cycle: u64) {
+        self.0.amplifier_event(enabled, cycle);
+        self.1.amplifier_event(enabled, cycle);
+    }
+
+    fn stop_one_shot_sounds(&mut self, cycle: u64) {
+        self.0.stop_one_shot_sounds(cycle);
+        self.1.stop_one_shot_sounds(cycle);
+    }
+
+    fn end_frame(&mut self, cycle: u64) {
+        self.0.end_frame(cycle);
+        self.1.end_frame(cycle);
+    }
+
+    fn set_volume(&mut self, volume: &Volume) {
+        self.0.set_volume(volume);
+        self.1.set_volume(volume);
+    }
+}
+
+/// A backend that plays the sounds from samples on a rodio audio stream.
+pub struct RodioBackend {
+    // Indexed by `SoundId`.
+    sounds: [Option<(Vec<u8>, Sink)>; 10],
+    // The volumes of the samples, and the gains of the volume controls.
+    volumes: [f32; 10],
+    gains: [f32; 10],
+    amplifier_enabled: bool,
+    // Whether the machine loops the sounds, and whether their samples repeat until stopped.
+    looping: [bool; 10],
+    repeating: [bool; 10],
+}
+
+impl RodioBackend {
+    /// Constructs a backend that plays the sounds of `machine` on the audio stream of
+    /// `audio_stream_handle`, from `samples` in the order of `SoundId::ALL`. The sound of a sample
+    /// that is `None` is synthesized.
+    pub fn new(
+        audio_stream_handle: &OutputStreamHandle,
+        samples: [Option<Sample>; 10],
+        machine: &Machine,
+    ) -> Self {
+        let looping = machine_looping(machine);
+        let mut repeating = looping;
+        let mut volumes = [1.0; 10];
+        let mut sounds = [(); 10].map(|_| None);
+        for ((id, sample), sound) in SoundId::ALL.into_iter().zip(samples).zip(&mut sounds) {
+            let sample = sample.unwrap_or_else(|| Sample::from(synth::wav(id)));
+            volumes[id as usize] = sample.volume;
+            repeating[id as usize] = sample.looping.unwrap_or(looping[id as usize]);
+            match Sink::try_new(audio_stream_handle) {
+                Ok(sink) => {
+                    // The amplifier is disabled at power-on.
+                    sink.set_volume(0.0);
+                    *sound = Some((sample.bytes, sink));
+                }
+                Err(err) => warn!("{:?}", err),
+            }
+        }
+        Self { sounds, volumes, gains: [1.0; 10], amplifier_enabled: false, looping, repeating }
+    }
+
+    /// Starts `sound`, which repeats until it is stopped if its sample does.
+    fn play(&self, sound: SoundId) {
+        if let Some((wav, sink)) = &self.sounds[sound as usize] {
+            match Decoder::new(Cursor::new(wav.clone())) {
+                Ok(source) if self.repeating[sound as usize] => {
+                    sink.append(source.repeat_infinite())
+                }
+                Ok(source) => sink.append(source),
+                Err(err) => warn!("{:?}", err),
+            }
+        }
+    }
+
+    fn stop(&self, sound: SoundId) {
+        if let Some((_, sink)) = &self.sounds[sound as usize] {
+            sink.stop();
+        }
+    }
+
+    fn update_sink_volumes(&self) {
+        for ((sound, volume), gain) in self.sounds.iter().zip(self.volumes).zip(self.gains) {
+            if let Some((_, sink)) = sound {
+                sink.set_volume(if self.amplifier_enabled { volume * gain } else { 0.0 });
+            }
+        }
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn sound_event(&mut self, event: SoundEvent) {
+        match event.on {
+            true => self.play(event.id),
+            false if self.repeating[event.id as usize] => self.stop(event.id),
+            false => (),
+        }
+    }
+
+    fn amplifier_event(&mut self, enabled: bool, _cycle: u64) {
+        self.amplifier_enabled = enabled;
+        self.update_sink_volumes();
+    }
+
+    fn stop_one_shot_sounds(&mut self, _cycle: u64) {
+        for sound in SoundId::ALL.into_iter().filter(|&sound| !self.looping[sound as usize]) {
+            self.stop(sound);
+        }
+    }
+
+    fn set_volume(&mut self, volume: &Volume) {
+        self.gains = SoundId::ALL.map(|sound| volume.gain(sound));
+        self.update_sink_volumes();
+    }
+}
+
+/// A backend that drops the sounds, as a machine without audio does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn sound_event(&mut self, _event: SoundEvent) {}
+
+    fn amplifier_event(&mut self, _enabled: bool, _cycle: u64) {}
+
+    fn stop_one_shot_sounds(&mut self, _cycle: u64) {}
+}
+
+/// A backend that records the sound events in memory, for instance to test which sounds a game
+/// triggers.
+///
+/// Its clones share the recording, so a clone kept aside can read the events received by the
+/// one given to the machine.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingBackend {
+    recording: Arc<Mutex<Recording>>,
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    events: Vec<SoundEvent>,
+    amplifier_enabled: bool,
+}
+
+impl RecordingBackend {
+    /// Constructs a backend with an empty recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the sound events received so far.
+    pub fn events(&self) -> Vec<SoundEvent> {
+        self.recording.lock().unwrap().events.clone()
+    }
+
+    /// Removes and returns the sound events received so far.
+    pub fn take_events(&self) -> Vec<SoundEvent> {
+        mem::take(&mut self.recording.lock().unwrap().events)
+    }
+
+    /// Returns `true` if the amplifier is enabled, so that the sounds would be heard.
+    pub fn is_amplifier_enabled(&self) -> bool {
+        self.recording.lock().unwrap().amplifier_enabled
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn sound_event(&mut self, event: SoundEvent) {
+        self.recording.lock().unwrap().events.push(event);
+    }
+
+    fn amplifier_event(&mut self, enabled: bool, _cycle: u64) {
+        self.recording.lock().unwrap().amplifier_enabled = enabled;
+    }
+
+    fn stop_one_shot_sounds(&mut self, _cycle: u64) {}
+}
+
+/// The sample rate of the WAV files written by [`WavRecorder`].
+const RECORDING_SAMPLE_RATE: u32 = 44_100;
+/// The number of states that the CPU executes per second of emulated time.
+const STATES_PER_SECOND: u64 = STATES_PER_FRAME as u64 * 60;
+
+/// A backend that mixes the sounds into a WAV file of 16-bit mono PCM at 44.1 kHz, as they are
+/// heard in emulated time rather than in wall-clock time.
+///
+/// The recording advances with the states that the machine executes, and goes on from where it
+/// is when the machine loads a state. The volumes of the samples apply to it, but the volume
+/// controls of the listener, a [`Volume`], do not. Its clones share the recording, so that a
+/// clone kept aside can [`finish`](WavRecorder::finish) it.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{fs::File, io::BufWriter};
+/// use space_invaders::{audio::WavRecorder, machine, SpaceInvaders};
+///
+/// let file = BufWriter::new(File::create("invaders.wav")?);
+/// let recorder = WavRecorder::new(file, Default::default(), &machine::INVADERS)?;
+/// let mut space_invaders = SpaceInvaders::builder()
+///     .rom_bytes(std::fs::read("invaders.h")?)
+///     .rom_bytes(std::fs::read("invaders.g")?)
+///     .rom_bytes(std::fs::read("invaders.f")?)
+///     .rom_bytes(std::fs::read("invaders.e")?)
+///     .audio_backend(recorder.clone())
+///     .build()?;
+/// for _ in 0..60 * 60 {
+///     space_invaders.run_frame()?;
+/// }
+/// recorder.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct WavRecorder<W> {
+    mixer: Arc<Mutex<Mixer<W>>>,
+}
+
+impl<W> Clone for WavRecorder<W> {
+    fn clone(&self) -> Self {
+        WavRecorder { mixer: Arc::clone(&self.mixer) }
+    }
+}
+
+struct Mixer<W> {
+    writer: W,
+    // Indexed by `SoundId`, at `RECORDING_SAMPLE_RATE`.
+    sounds: [Vec<f32>; 10],
+    looping: [bool; 10],
+    repeating: [bool; 10],
+    // The positions in `sounds` of those being played.
+    positions: [Option<usize>; 10],
+    amplifier_enabled: bool,
+    // The cycle of the machine that the recording is at, and the number of states recorded.
+    cycle: u64,
+    states: u64,
+    // The number of samples written.
+    len: u64,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Seek + Send> WavRecorder<W> {
+    /// Constructs a backend that writes the sounds of `machine`, from `samples` in the order of
+    /// `SoundId::ALL`, to `writer`. The sound of a sample that is `None` is synthesized.
+    pub fn new(
+        mut writer: W,
+        samples: [Option<Sample>; 10],
+        machine: &Machine,
+    ) -> io::Result<Self> {
+        // The sizes in the header are written by `finish`.
+        writer.write_all(&synth::wav_header(RECORDING_SAMPLE_RATE, 0))?;
+        let looping = machine_looping(machine);
+        let mut repeating = looping;
+        let mut sounds: [Vec<f32>; 10] = Default::default();
+        for ((id, sample), sound) in SoundId::ALL.into_iter().zip(samples).zip(&mut sounds) {
+            let sample = sample.unwrap_or_else(|| Sample::from(synth::wav(id)));
+            repeating[id as usize] = sample.looping.unwrap_or(looping[id as usize]);
+            match Decoder::new(Cursor::new(sample.bytes)) {
+                Ok(decoder) => {
+                    *sound = resample(decoder).into_iter().map(|x| x * sample.volume).collect();
+                }
+                Err(err) => warn!("{:?}", err),
+            }
+        }
+        let mixer = Mixer {
+            writer,
+            sounds,
+            looping,
+            repeating,
+            positions: [None; 10],
+            amplifier_enabled: false,
+            cycle: 0,
+            states: 0,
+            len: 0,
+            finished: false,
+            error: None,
+        };
+        Ok(WavRecorder { mixer: Arc::new(Mutex::new(mixer)) })
+    }
+
+    /// Writes the sizes in the header of the WAV file and flushes it, after which the sounds are
+    /// no longer recorded. Fails with the first error that occurred while writing the file, if
+    /// any.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut mixer = self.mixer.lock().unwrap();
+        mixer.finished = true;
+        if let Some(err) = mixer.error.take() {
+            return Err(err);
+        }
+        let data_len = u32::try_from(mixer.len * 2).unwrap_or(u32::MAX);
+        mixer.writer.seek(SeekFrom::Start(0))?;
+        mixer.writer.write_all(&synth::wav_header(RECORDING_SAMPLE_RATE, data_len))?;
+        mixer.writer.seek(SeekFrom::End(0))?;
+        mixer.writer.flush()
+    }
+}
+
+impl<W: Write> Mixer<W> {
+    /// Mixes the sounds being played into the file up to `cycle`.
+    fn mix_until(&mut self, cycle: u64) {
+        if self.finished {
+            return;
+        }
+        self.states += cycle.saturating_sub(self.cycle);
+        self.cycle = cycle;
+        let len = self.states * u64::from(RECORDING_SAMPLE_RATE) / STATES_PER_SECOND;
+        let mut data = Vec::new();
+        while self.len < len {
+            let mut mix = 0.0;
+            for ((sound, position), repeating) in
+                self.sounds.iter().zip(&mut self.positions).zip(self.repeating)
+            {
+                let Some(index) = position else { continue };
+                mix += sound.get(*index).copied().unwrap_or_default();
+                *index += 1;
+                if *index >= sound.len() {
+                    *position = if repeating && !sound.is_empty() { Some(0) } else { None };
+                }
+            }
+            let sample = if self.amplifier_enabled { mix.clamp(-1.0, 1.0) } else { 0.0 };
+            data.extend_from_slice(&((sample * f32::from(i16::MAX)) as i16).to_le_bytes());
+            self.len += 1;
+        }
+        if self.error.is_none() {
+            self.error = self.writer.write_all(&data).err();
+        }
+    }
+}
+
+impl<W: Write + Send> AudioBackend for WavRecorder<W> {
+    fn sound_event(&mut self, event: SoundEvent) {
+        let mut mixer = self.mixer.lock().unwrap();
+        mixer.mix_until(event.cycle);
+        let id = event.id as usize;
+        if event.on {
+            mixer.positions[id] = Some(0);
+        } else if mixer.repeating[id] {
+            mixer.positions[id] = None;
+        }
+    }
+
+    fn amplifier_event(&mut self, enabled: bool, cycle: u64) {
+        let mut mixer = self.mixer.lock().unwrap();
+        mixer.mix_until(cycle);
+        mixer.amplifier_enabled = enabled;
+    }
+
+    fn stop_one_shot_sounds(&mut self, cycle: u64) {
+        let mut mixer = self.mixer.lock().unwrap();
+        // The recording goes on from the point in time that the machine jumps to.
+        mixer.cycle = cycle;
+        let looping = mixer.looping;
+        for (position, looping) in mixer.positions.iter_mut().zip(looping) {
+            if !looping {
+                *position = None;
+            }
+        }
+    }
+
+    fn end_frame(&mut self, cycle: u64) {
+        self.mixer.lock().unwrap().mix_until(cycle);
+    }
+}
+
+/// Mixes the channels of `source` down to mono and resamples it linearly to
+/// `RECORDING_SAMPLE_RATE`.
+fn resample<S: Source<Item = i16>>(source: S) -> Vec<f32> {
+    let channels = usize::from(source.channels().max(1));
+    let sample_rate = source.sample_rate().max(1);
+    let samples: Vec<i16> = source.collect();
+    let mono: Vec<f32> = samples
+        .chunks(channels)
+        .map(|frame| {
+            let sum: f32 = frame.iter().map(|&sample| f32::from(sample)).sum();
+            sum / channels as f32 / f32::from(i16::MAX)
+        })
+        .collect();
+    let len = mono.len() as u64 * u64::from(RECORDING_SAMPLE_RATE) / u64::from(sample_rate);
+    let step = sample_rate as f32 / RECORDING_SAMPLE_RATE as f32;
+    (0..len)
+        .map(|i| {
+            let position = i as f32 * step;
+            let index = position as usize;
+            let next = mono.get(index + 1).copied().unwrap_or(0.0);
+            let fraction = position.fract();
+            mono[index] * (1.0 - fraction) + next * fraction
+        })
+        .collect()
+}
+
+/// Returns whether `machine` loops each sound, by `SoundId`.
+fn machine_looping(machine: &Machine) -> [bool; 10] {
+    let mut looping = [false; 10];
+    for output in machine.sounds.iter().filter(|output| output.looping) {
+        looping[output.sound as usize] = true;
+    }
+    looping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    // MVI A,$22; OUT 3; MVI A,$31; OUT 3; MVI A,$01; OUT 3; MVI A,$11; OUT 5; JMP $0010
+    const PROGRAM: [u8; 19] = [
+        0x3E, 0x22, 0xD3, 0x03, 0x3E, 0x31, 0xD3, 0x03, 0x3E, 0x01, 0xD3, 0x03, 0x3E, 0x11, 0xD3,
+        0x05, 0xC3, 0x10, 0x00,
+    ];
+
+    fn event(id: SoundId, on: bool, cycle: u64) -> SoundEvent {
+        SoundEvent { id, on, cycle }
+    }
+
+    #[test]
+    fn recording_backend() {
+        let recorder = RecordingBackend::new();
+        let mut space_invaders = builder(&PROGRAM).audio_backend(recorder.clone()).build().unwrap();
+        assert!(!recorder.is_amplifier_enabled());
+
+        // The shot, with the amplifier enabled.
+        space_invaders.update().unwrap();
+        space_invaders.update().unwrap();
+        assert!(recorder.is_amplifier_enabled());
+        assert_eq!(recorder.take_events(), [event(SoundId::Shoot, true, 17)]);
+
+        // The UFO and the extended play start as the shot bit falls.
+        space_invaders.update().unwrap();
+        space_invaders.update().unwrap();
+        assert_eq!(
+            recorder.take_events(),
+            [
+                event(SoundId::UfoLowPitch, true, 34),
+                event(SoundId::Shoot, false, 34),
+                event(SoundId::ExtendedPlay, true, 34),
+            ]
+        );
+
+        // The amplifier is disabled, which mutes the UFO still looping.
+        space_invaders.update().unwrap();
+        space_invaders.update().unwrap();
+        assert!(!recorder.is_amplifier_enabled());
+        assert_eq!(recorder.take_events(), [event(SoundId::ExtendedPlay, false, 51)]);
+
+        space_invaders.update().unwrap();
+        space_invaders.update().unwrap();
+        assert_eq!(
+            recorder.events(),
+            [event(SoundId::FastInvader1, true, 68), event(SoundId::UfoHighPitch, true, 68)]
+        );
+        // The same bits written again trigger nothing.
+        space_invaders.i8080.cpu.pc = 0x0E;
+        space_invaders.update().unwrap();
+        assert_eq!(recorder.events().len(), 2);
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Call {
+        Sound(SoundEvent),
+        Amplifier(bool, u64),
+        StopOneShotSounds(u64),
+    }
+
+    /// A backend that records every call.
+    #[derive(Clone, Default)]
+    struct Probe(Arc<Mutex<Vec<Call>>>);
+
+    impl Probe {
+        fn take_calls(&self) -> Vec<Call> {
+            mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl AudioBackend for Probe {
+        fn sound_event(&mut self, event: SoundEvent) {
+            self.0.lock().unwrap().push(Call::Sound(event));
+        }
+
+        fn amplifier_event(&mut self, enabled: bool, cycle: u64) {
+            self.0.lock().unwrap().push(Call::Amplifier(enabled, cycle));
+        }
+
+        fn stop_one_shot_sounds(&mut self, cycle: u64) {
+            self.0.lock().unwrap().push(Call::StopOneShotSounds(cycle));
+        }
+    }
+
+    #[test]
+    fn load_state_stops_one_shot_sounds() {
+        let probe = Probe::default();
+        let mut space_invaders = builder(&PROGRAM).audio_backend(probe.clone()).build().unwrap();
+        space_invaders.update().unwrap();
+        space_invaders.update().unwrap();
+        let mut state = Vec::new();
+        space_invaders.save_state(&mut state).unwrap();
+        for _ in 0..4 {
+            space_invaders.update().unwrap();
+        }
+        probe.take_calls();
+
+        // The UFO, looping when the state is loaded, stops as its bit was cleared in the state.
+        space_invaders.load_state(&state[..]).unwrap();
+        assert_eq!(
+            probe.take_calls(),
+            [
+                Call::StopOneShotSounds(17),
+                Call::Sound(event(SoundId::UfoLowPitch, false, 17)),
+                Call::Amplifier(true, 17),
+            ]
+        );
+    }
+
+    #[test]
+    fn null_backend() {
+        let recorder = RecordingBackend::new();
+        let mut recorded = builder(&PROGRAM).audio_backend(recorder).build().unwrap();
+        let mut silent = builder(&PROGRAM).audio_backend(NullBackend).build().unwrap();
+        for _ in 0..10 {
+            assert_eq!(silent.update().unwrap(), recorded.update().unwrap());
+        }
+        assert_eq!(silent.state_hash(), recorded.state_hash());
+
+        let mut backend = NullBackend;
+        backend.sound_event(event(SoundId::Shoot, true, 0));
+        backend.amplifier_event(true, 0);
+        backend.stop_one_shot_sounds(0);
+        backend.end_frame(0);
+        backend.set_volume(&Volume::default());
+    }
+}
@@ -0,0 +1,170 @@
+//! A builder of machines from in-memory ROMs and samples.
+
+use rodio::OutputStreamHandle;
+
+use i8080::Intel8080;
+
+use crate::{
+    audio::{AudioBackend, NullBackend, RodioBackend, Volume},
+    machine::{self, Machine, SoundId},
+    sample_set::Sample,
+    Error, FrameInfo, InterruptScheduler, InterruptSource, MemoryMap, Port1, Port2, Result,
+    SpaceInvaders, VideoInterrupts, VideoShifter,
+};
+
+/// A builder of [`SpaceInvaders`], as returned by [`SpaceInvaders::builder`].
+///
+/// The machine is Space Invaders unless another one is given, and has no sound unless an audio
+/// stream is given, in which case the sounds without samples are synthesized. Nothing is read
+/// from the filesystem.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::SpaceInvaders;
+///
+/// // MVI A,$55; STA $2400; JMP $0005
+/// let mut invaders_h = vec![0; 0x800];
+/// invaders_h[..8].copy_from_slice(&[0x3E, 0x55, 0x32, 0x00, 0x24, 0xC3, 0x05, 0x00]);
+/// let mut space_invaders = SpaceInvaders::builder()
+///     .rom_bytes(invaders_h)
+///     .rom_bytes(vec![0; 0x800])
+///     .rom_bytes(vec![0; 0x800])
+///     .rom_bytes(vec![0; 0x800])
+///     .build()?;
+/// space_invaders.update()?;
+/// space_invaders.update()?;
+/// assert_eq!(space_invaders.framebuffer()[0], 0x55);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Builder<'a> {
+    machine: &'static Machine,
+    roms: Vec<Vec<u8>>,
+    samples: [Option<Sample>; 10],
+    audio_stream_handle: Option<&'a OutputStreamHandle>,
+    audio_backend: Option<Box<dyn AudioBackend>>,
+    interrupt_source: Box<dyn InterruptSource>,
+}
+
+impl<'a> Builder<'a> {
+    pub(crate) fn new() -> Self {
+        Self {
+            machine: &machine::INVADERS,
+            roms: Vec::new(),
+            samples: Default::default(),
+            audio_stream_handle: None,
+            audio_backend: None,
+            interrupt_source: Box::new(VideoInterrupts),
+        }
+    }
+
+    /// Sets the board, `machine::INVADERS` by default.
+    pub fn machine(mut self, machine: &'static Machine) -> Self {
+        self.machine = machine;
+        self
+    }
+
+    /// Adds the contents of the next ROM of the machine, in the order of `Machine::roms`.
+    pub fn rom_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.roms.push(bytes.into());
+        self
+    }
+
+    /// Sets the contents of the sample played for `sound`, in a format that rodio can decode.
+    pub fn sample_bytes(mut self, sound: SoundId, bytes: impl Into<Vec<u8>>) -> Self {
+        self.samples[sound as usize] = Some(Sample::from(bytes.into()));
+        self
+    }
+
+    /// Sets the sample played for `sound`, with its volume and whether it loops.
+    pub fn sample(mut self, sound: SoundId, sample: Sample) -> Self {
+        self.samples[sound as usize] = Some(sample);
+        self
+    }
+
+    /// Plays the samples on the audio stream of `audio_stream_handle`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::fs;
+    /// use rodio::OutputStream;
+    /// use space_invaders::{machine::SoundId, SpaceInvaders};
+    ///
+    /// let (_audio_stream, audio_stream_handle) = OutputStream::try_default()?;
+    /// let space_invaders = SpaceInvaders::builder()
+    ///     .rom_bytes(fs::read("invaders.h")?)
+    ///     .rom_bytes(fs::read("invaders.g")?)
+    ///     .rom_bytes(fs::read("invaders.f")?)
+    ///     .rom_bytes(fs::read("invaders.e")?)
+    ///     .sample_bytes(SoundId::Shoot, fs::read("1.wav")?)
+    ///     .audio(&audio_stream_handle)
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn audio(mut self, audio_stream_handle: &'a OutputStreamHandle) -> Self {
+        self.audio_stream_handle = Some(audio_stream_handle);
+        self.audio_backend = None;
+        self
+    }
+
+    /// Sends the sounds to `audio_backend` instead of playing the samples on an audio stream.
+    pub fn audio_backend(mut self, audio_backend: impl AudioBackend + 'static) -> Self {
+        self.audio_backend = Some(Box::new(audio_backend));
+        self.audio_stream_handle = None;
+        self
+    }
+
+    /// Sets the source of the interrupts, `VideoInterrupts` by default.
+    pub fn interrupt_source(mut self, interrupt_source: impl InterruptSource + 'static) -> Self {
+        self.interrupt_source = Box::new(interrupt_source);
+        self
+    }
+
+    /// Constructs the `SpaceInvaders`.
+    ///
+    /// Fails if the number of ROMs is not that of the machine, or if a ROM is larger than its
+    /// region.
+    pub fn build(self) -> Result<SpaceInvaders> {
+        let machine = self.machine;
+        if self.roms.len() != machine.roms.len() {
+            return Err(Error::InvalidRoms { reason: "wrong number of ROMs for the machine" });
+        }
+        let mut i8080 = Intel8080::new::<&str>(&[], 0)?;
+        for (bytes, rom) in self.roms.iter().zip(machine.roms) {
+            if bytes.len() > usize::from(rom.len) {
+                return Err(Error::InvalidRoms { reason: "ROM larger than its region" });
+            }
+            let address = usize::from(rom.address);
+            i8080.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        }
+        let regions = machine.roms.iter().map(|rom| (rom.address, rom.len)).collect::<Vec<_>>();
+        let memory_map = MemoryMap::new(&i8080.memory, &regions);
+        let rom_hashes =
+            memory_map.roms().flat_map(|rom| rom.chunks(0x800)).map(crc32fast::hash).collect();
+        let audio = match (self.audio_backend, self.audio_stream_handle) {
+            (Some(audio_backend), _) => audio_backend,
+            (None, Some(handle)) => Box::new(RodioBackend::new(handle, self.samples, machine)),
+            (None, None) => Box::new(NullBackend),
+        };
+        Ok(SpaceInvaders {
+            i8080,
+            machine,
+            memory_map,
+            interrupt_source: self.interrupt_source,
+            interrupt_scheduler: InterruptScheduler::default(),
+            port1: Port1::default(),
+            port2: Port2::default(),
+            sound_latches: [0; 2],
+            video_shifter: VideoShifter::default(),
+            audio,
+            volume: Volume::default(),
+            frame_info: FrameInfo::default(),
+            rom_hashes,
+            unmapped_io_policy: machine.unmapped_io_policy,
+            watchdog_frames: 0,
+            events: Vec::new(),
+            pending_high_score: None,
+        })
+    }
+}
@@ -0,0 +1,449 @@
+//! An interactive debugger for the Intel 8080 CPU.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use i8080::ConditionFlags;
+
+use crate::{
+    disassembler::{self, disassemble},
+    SpaceInvaders,
+};
+
+const HELP: &str = "\
+s, step [N]          execute N instructions (default: 1)
+n, next              execute an instruction, stepping over calls and restarts
+c, continue          resume the machine until a breakpoint is hit
+b, break ADDR        set a breakpoint at ADDR
+d, delete ADDR       delete the breakpoint at ADDR
+i, info              list the breakpoints
+r, registers         dump the registers
+x, examine ADDR [N]  dump N bytes of memory from ADDR (default: 64)
+l, list [ADDR]       disassemble around ADDR (default: PC)
+q, quit              quit the emulator
+h, help              print this help
+
+Addresses and byte counts are hexadecimal, with an optional `$` or `0x` prefix.
+";
+
+/// A debugger command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Executes the given number of instructions.
+    Step(u32),
+    /// Executes an instruction, stepping over calls and restarts.
+    Next,
+    /// Resumes the machine until a breakpoint is hit.
+    Continue,
+    /// Sets a breakpoint.
+    Break(u16),
+    /// Deletes a breakpoint.
+    Delete(u16),
+    /// Lists the breakpoints.
+    Info,
+    /// Dumps the registers.
+    Registers,
+    /// Dumps the given number of bytes of memory from an address.
+    Examine(u16, u16),
+    /// Disassembles around an address, or around PC if `None`.
+    List(Option<u16>),
+    /// Quits the emulator.
+    Quit,
+    /// Prints the list of commands.
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = match words.next().unwrap_or("") {
+            "s" | "step" => Command::Step(match words.next() {
+                Some(count) => count.parse().map_err(|_| format!("invalid count: {count}"))?,
+                None => 1,
+            }),
+            "n" | "next" => Command::Next,
+            "c" | "continue" => Command::Continue,
+            "b" | "break" => Command::Break(parse_hex(words.next())?),
+            "d" | "delete" => Command::Delete(parse_hex(words.next())?),
+            "i" | "info" => Command::Info,
+            "r" | "registers" => Command::Registers,
+            "x" | "examine" => Command::Examine(
+                parse_hex(words.next())?,
+                words.next().map_or(Ok(0x40), |len| parse_hex(Some(len)))?,
+            ),
+            "l" | "list" => {
+                Command::List(words.next().map(|addr| parse_hex(Some(addr))).transpose()?)
+            }
+            "q" | "quit" => Command::Quit,
+            "h" | "help" => Command::Help,
+            "" => return Err("no command".to_string()),
+            command => return Err(format!("unknown command: {command} (try `help`)")),
+        };
+        match words.next() {
+            Some(word) => Err(format!("unexpected argument: {word}")),
+            None => Ok(command),
+        }
+    }
+}
+
+fn parse_hex(word: Option<&str>) -> Result<u16, String> {
+    let word = word.ok_or_else(|| "missing address".to_string())?;
+    let digits = word.strip_prefix('$').or_else(|| word.strip_prefix("0x")).unwrap_or(word);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hexadecimal number: {word}"))
+}
+
+/// An interactive debugger, which steps a [`SpaceInvaders`] instruction by instruction and stops
+/// it at breakpoints.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io;
+/// use space_invaders::{
+///     debugger::{Command, Debugger},
+///     SpaceInvaders,
+/// };
+///
+/// let mut space_invaders = SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?;
+/// let mut debugger = Debugger::new();
+/// debugger.execute(Command::Break(0x0008), &mut space_invaders, io::stdout())?;
+/// while debugger.run_frame(&mut space_invaders)?.is_none() {}
+/// debugger.execute(Command::Registers, &mut space_invaders, io::stdout())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    // The breakpoint set by `Command::Next`, which is deleted once the machine stops.
+    temporary_breakpoint: Option<u16>,
+    // The address from which the machine has been resumed, whose breakpoint must not stop it
+    // again before it executes an instruction.
+    resumed_at: Option<u16>,
+}
+
+impl Debugger {
+    /// Constructs a new `Debugger` with no breakpoints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes `command` on `space_invaders`, writing the output to `out`.
+    ///
+    /// Returns `true` if the machine has to be resumed with [`Debugger::run_frame`]. The caller
+    /// is responsible for `Command::Quit`, which does nothing here. Fails if writing to `out`
+    /// fails or if an instruction stepped fails, in which case the machine stops there.
+    pub fn execute<W: Write>(
+        &mut self,
+        command: Command,
+        space_invaders: &mut SpaceInvaders,
+        mut out: W,
+    ) -> crate::Result<bool> {
+        let memory = &space_invaders.i8080.memory[..];
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    space_invaders.update()?;
+                }
+                self.write_location(space_invaders, &mut out)?;
+            }
+            Command::Next => {
+                let pc = space_invaders.i8080.cpu.pc;
+                if disassembler::is_call(memory, pc) {
+                    self.temporary_breakpoint = Some(disassembler::next_address(memory, pc));
+                    self.resumed_at = Some(pc);
+                    return Ok(true);
+                }
+                space_invaders.update()?;
+                self.write_location(space_invaders, &mut out)?;
+            }
+            Command::Continue => {
+                self.resumed_at = Some(space_invaders.i8080.cpu.pc);
+                return Ok(true);
+            }
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+                writeln!(out, "breakpoint at ${address:04X}")?;
+            }
+            Command::Delete(address) => {
+                if self.breakpoints.remove(&address) {
+                    writeln!(out, "deleted breakpoint at ${address:04X}")?;
+                } else {
+                    writeln!(out, "no breakpoint at ${address:04X}")?;
+                }
+            }
+            Command::Info => {
+                if self.breakpoints.is_empty() {
+                    writeln!(out, "no breakpoints")?;
+                }
+                for address in &self.breakpoints {
+                    let (instruction, _) = disassemble(memory, *address);
+                    writeln!(out, "${address:04X}  {instruction}")?;
+                }
+            }
+            Command::Registers => {
+                let cpu = &space_invaders.i8080.cpu;
+                let flags = cpu.condition_flags;
+                let flag = |flag, name| if flags.contains(flag) { name } else { '-' };
+                writeln!(
+                    out,
+                    "A=${:02X} B=${:02X} C=${:02X} D=${:02X} E=${:02X} H=${:02X} L=${:02X} \
+                     SP=${:04X} PC=${:04X}",
+                    cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, cpu.pc,
+                )?;
+                writeln!(
+                    out,
+                    "F=${:02X} [{}{}{}{}{}] INTE={} frame={} state={}",
+                    flags.bits(),
+                    flag(ConditionFlags::SIGN, 'S'),
+                    flag(ConditionFlags::ZERO, 'Z'),
+                    flag(ConditionFlags::AUX_CARRY, 'A'),
+                    flag(ConditionFlags::PARITY, 'P'),
+                    flag(ConditionFlags::CARRY, 'C'),
+                    u8::from(cpu.interrupt_enabled),
+                    space_invaders.frame_count(),
+                    space_invaders.interrupt_scheduler.frame_states,
+                )?;
+            }
+            Command::Examine(address, len) => {
+                for line_address in (0..len).step_by(16).map(|offset| address.wrapping_add(offset))
+                {
+                    let line_len = (len - line_address.wrapping_sub(address)).min(16);
+                    let bytes = (0..line_len)
+                        .map(|i| memory[usize::from(line_address.wrapping_add(i))])
+                        .collect::<Vec<_>>();
+                    write!(out, "${line_address:04X} ")?;
+                    for byte in &bytes {
+                        write!(out, " {byte:02X}")?;
+                    }
+                    write!(out, "{:1$}  ", "", (16 - bytes.len()) * 3)?;
+                    let text = bytes
+                        .iter()
+                        .map(|&byte| if byte.is_ascii_graphic() { char::from(byte) } else { '.' })
+                        .collect::<String>();
+                    writeln!(out, "{text}")?;
+                }
+            }
+            Command::List(address) => {
+                let pc = space_invaders.i8080.cpu.pc;
+                let address = address.unwrap_or(pc);
+                let mut line_address = listing_start(memory, address);
+                for _ in 0..12 {
+                    let (instruction, len) = disassemble(memory, line_address);
+                    let marker = if line_address == pc { "=>" } else { "  " };
+                    let breakpoint =
+                        if self.breakpoints.contains(&line_address) { '*' } else { ' ' };
+                    writeln!(out, "{marker}{breakpoint}${line_address:04X}  {instruction}")?;
+                    line_address = line_address.wrapping_add(len);
+                }
+            }
+            Command::Quit => (),
+            Command::Help => write!(out, "{HELP}")?,
+        }
+        Ok(false)
+    }
+
+    /// Runs `space_invaders` until the current video frame is completed or a breakpoint is hit.
+    ///
+    /// Returns the address of the breakpoint if one has been hit. Fails if an instruction fails,
+    /// in which case the machine stops there.
+    pub fn run_frame(&mut self, space_invaders: &mut SpaceInvaders) -> crate::Result<Option<u16>> {
+        let frame_count = space_invaders.frame_count();
+        while space_invaders.frame_count() == frame_count {
+            let pc = space_invaders.i8080.cpu.pc;
+            if self.resumed_at.take() != Some(pc)
+                && (self.breakpoints.contains(&pc) || self.temporary_breakpoint == Some(pc))
+            {
+                self.temporary_breakpoint = None;
+                return Ok(Some(pc));
+            }
+            space_invaders.update()?;
+        }
+        Ok(None)
+    }
+
+    /// Writes the current PC and the instruction there to `out`.
+    pub fn write_location<W: Write>(
+        &self,
+        space_invaders: &SpaceInvaders,
+        mut out: W,
+    ) -> io::Result<()> {
+        let pc = space_invaders.i8080.cpu.pc;
+        let (instruction, _) = disassemble(&space_invaders.i8080.memory, pc);
+        writeln!(out, "=>${pc:04X}  {instruction}")
+    }
+}
+
+/// Returns an address a few instructions before `address` from which disassembly reaches
+/// `address`.
+fn listing_start(memory: &[u8], address: u16) -> u16 {
+    // Instructions are variable in length, so disassembly can only be synchronized by trying
+    // starting points one by one.
+    (1..=12)
+        .rev()
+        .map(|distance| address.wrapping_sub(distance))
+        .find(|&start| {
+            let mut line_address = start;
+            let mut lines = 0;
+            while line_address != address && lines < 4 {
+                line_address = disassembler::next_address(memory, line_address);
+                lines += 1;
+                if line_address.wrapping_sub(start) > address.wrapping_sub(start) {
+                    return false;
+                }
+            }
+            line_address == address
+        })
+        .unwrap_or(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    fn execute(
+        debugger: &mut Debugger,
+        command: Command,
+        space_invaders: &mut SpaceInvaders,
+    ) -> (bool, String) {
+        let mut out = Vec::new();
+        let resume = debugger.execute(command, space_invaders, &mut out).unwrap();
+        (resume, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parse_commands() {
+        for (line, command) in [
+            ("s", Command::Step(1)),
+            ("step 10", Command::Step(10)),
+            ("n", Command::Next),
+            ("  continue  ", Command::Continue),
+            ("b $18D4", Command::Break(0x18D4)),
+            ("delete 0x18d4", Command::Delete(0x18D4)),
+            ("i", Command::Info),
+            ("r", Command::Registers),
+            ("x 2400", Command::Examine(0x2400, 0x40)),
+            ("examine $2400 10", Command::Examine(0x2400, 0x10)),
+            ("l", Command::List(None)),
+            ("list 8", Command::List(Some(0x0008))),
+            ("q", Command::Quit),
+            ("help", Command::Help),
+        ] {
+            assert_eq!(line.parse(), Ok(command), "{line}");
+        }
+        for (line, err) in [
+            ("", "no command"),
+            ("go", "unknown command: go (try `help`)"),
+            ("s ten", "invalid count: ten"),
+            ("b", "missing address"),
+            ("b 10000", "invalid hexadecimal number: 10000"),
+            ("x 2400 $zz", "invalid hexadecimal number: $zz"),
+            ("x 2400 10 20", "unexpected argument: 20"),
+        ] {
+            assert_eq!(line.parse::<Command>(), Err(err.to_string()), "{line}");
+        }
+    }
+
+    #[test]
+    fn step_and_next() {
+        // RST 1; NOP, with RET at RST 1
+        let mut program = [0; 9];
+        program[0] = 0xCF;
+        program[8] = 0xC9;
+        let mut space_invaders = builder(&program).build().unwrap();
+        space_invaders.i8080.cpu.sp = 0x2400;
+        let mut debugger = Debugger::new();
+        let location = |location: &str| (false, format!("=>{location}\n"));
+        let step = execute(&mut debugger, Command::Step(1), &mut space_invaders);
+        assert_eq!(step, location("$0008  RET"));
+        let step = execute(&mut debugger, Command::Step(2), &mut space_invaders);
+        assert_eq!(step, location("$0002  NOP"));
+        // Any other instruction is stepped.
+        let next = execute(&mut debugger, Command::Next, &mut space_invaders);
+        assert_eq!(next, location("$0003  NOP"));
+
+        // A restart is stepped over by resuming the machine until it returns.
+        space_invaders.i8080.cpu.pc = 0x0000;
+        let next = execute(&mut debugger, Command::Next, &mut space_invaders);
+        assert_eq!(next, (true, String::new()));
+        assert_eq!(debugger.run_frame(&mut space_invaders).unwrap(), Some(0x0001));
+        assert_eq!(space_invaders.i8080.cpu.sp, 0x2400);
+        // The breakpoint set to step over it is deleted once hit.
+        assert_eq!(debugger.run_frame(&mut space_invaders).unwrap(), None);
+    }
+
+    #[test]
+    fn breakpoints() {
+        // NOP; NOP; NOP; NOP; JMP $0000
+        let mut space_invaders =
+            builder(&[0x00, 0x00, 0x00, 0x00, 0xC3, 0x00, 0x00]).build().unwrap();
+        let mut debugger = Debugger::new();
+        let mut output = |command| execute(&mut debugger, command, &mut space_invaders).1;
+        assert_eq!(output(Command::Info), "no breakpoints\n");
+        assert_eq!(output(Command::Break(0x0002)), "breakpoint at $0002\n");
+        output(Command::Break(0x0004));
+        assert_eq!(output(Command::Info), "$0002  NOP\n$0004  JMP $0000\n");
+
+        // The machine resumed from a breakpoint executes its instruction before it stops again.
+        for breakpoint in [0x0002, 0x0004, 0x0002] {
+            execute(&mut debugger, Command::Continue, &mut space_invaders);
+            assert_eq!(debugger.run_frame(&mut space_invaders).unwrap(), Some(breakpoint));
+        }
+        let mut output = |command| execute(&mut debugger, command, &mut space_invaders).1;
+        assert_eq!(output(Command::Delete(0x0002)), "deleted breakpoint at $0002\n");
+        assert_eq!(output(Command::Delete(0x0002)), "no breakpoint at $0002\n");
+        output(Command::Delete(0x0004));
+        output(Command::Continue);
+        assert_eq!(debugger.run_frame(&mut space_invaders).unwrap(), None);
+        assert_eq!(space_invaders.frame_count(), 1);
+    }
+
+    #[test]
+    fn registers() {
+        let mut space_invaders = builder(&[]).build().unwrap();
+        let cpu = &mut space_invaders.i8080.cpu;
+        (cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l) =
+            (0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD);
+        (cpu.sp, cpu.pc) = (0x2400, 0x18D4);
+        cpu.condition_flags = ConditionFlags::ZERO | ConditionFlags::CARRY;
+        assert_eq!(
+            execute(&mut Debugger::new(), Command::Registers, &mut space_invaders).1,
+            "A=$01 B=$23 C=$45 D=$67 E=$89 H=$AB L=$CD SP=$2400 PC=$18D4\n\
+             F=$41 [-Z--C] INTE=0 frame=0 state=0\n"
+        );
+    }
+
+    #[test]
+    fn examine() {
+        let mut space_invaders = builder(&[]).build().unwrap();
+        space_invaders.i8080.memory[0x2400..0x2414]
+            .copy_from_slice(b"Space Invaders\0\x01\x02\x03\x04~");
+        let mut examine = |address, len| {
+            execute(&mut Debugger::new(), Command::Examine(address, len), &mut space_invaders).1
+        };
+        assert_eq!(
+            examine(0x2400, 0x14),
+            format!(
+                "$2400  53 70 61 63 65 20 49 6E 76 61 64 65 72 73 00 01  Space.Invaders..\n\
+                 $2410  02 03 04 7E{:36}  ...~\n",
+                ""
+            )
+        );
+        assert_eq!(examine(0x2400, 0x40).lines().count(), 4);
+        assert_eq!(examine(0x2400, 0x41).lines().count(), 5);
+        // A dump that wraps around the end of memory.
+        assert_eq!(
+            examine(0xFFF8, 0x10).lines().collect::<Vec<_>>(),
+            [format!("$FFF8 {}  {}", " 00".repeat(16), ".".repeat(16))]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! The settings of the DIP switches, as an operator would set them.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    machine::{DipSwitch, Machine},
+    Error, Port2, Result,
+};
+
+/// The settings of the DIP switches of a machine, which are bits of `Port2`.
+///
+/// The settings are not tied to a machine. A machine without a switch for one of them only
+/// accepts its factory setting.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::{machine, DipSwitches, Port2};
+///
+/// let dip_switches = DipSwitches { lives: 5, extra_life_at: 1000, coin_info: false };
+/// let port2 = dip_switches.to_port2(&machine::INVADERS)?;
+/// assert_eq!(port2, Port2::from_bits_truncate(0b1000_1010));
+/// assert_eq!(DipSwitches::from_port2(&machine::INVADERS, port2), dip_switches);
+/// # Ok::<(), space_invaders::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DipSwitches {
+    /// The number of lives at the start of a game.
+    pub lives: u8,
+    /// The score at which the extra life is awarded.
+    pub extra_life_at: u16,
+    /// Whether the price of a game is displayed in the attract mode.
+    pub coin_info: bool,
+}
+
+impl Default for DipSwitches {
+    /// Returns the factory settings of Space Invaders: 3 lives, an extra life at 1500 points and
+    /// the coin info displayed.
+    fn default() -> Self {
+        DipSwitches { lives: 3, extra_life_at: 1500, coin_info: true }
+    }
+}
+
+impl DipSwitches {
+    /// Returns the settings that `port2` selects on `machine`. A setting without a switch, or
+    /// whose switch is set to an unknown value, is the factory setting.
+    pub fn from_port2(machine: &Machine, port2: Port2) -> Self {
+        let mut dip_switches = DipSwitches::default();
+        for dip_switch in machine.dip_switches {
+            if let Some(setting) = dip_switch.setting(port2.bits()) {
+                dip_switches.set_value(dip_switch.key, dip_switch.settings[setting].1);
+            }
+        }
+        dip_switches
+    }
+
+    /// Returns the DIP switch bits of `Port2` that select the settings on `machine`; the other
+    /// bits are cleared.
+    ///
+    /// Fails with `Error::UnsupportedDipSwitch` if `machine` has no switch for a setting that is
+    /// not the factory setting, or if the switch cannot be set to it.
+    pub fn to_port2(&self, machine: &Machine) -> Result<Port2> {
+        let mut bits = 0;
+        for key in DipSwitchKey::ALL {
+            let value = self.value(key);
+            match machine.dip_switches.iter().find(|dip_switch| dip_switch.key == key) {
+                Some(dip_switch) => match bits_of(dip_switch, value) {
+                    Some(dip_switch_bits) => bits |= dip_switch_bits,
+                    None => return Err(self.unsupported(key)),
+                },
+                None if value == DipSwitches::default().value(key) => (),
+                None => return Err(self.unsupported(key)),
+            }
+        }
+        Ok(Port2::from_bits_truncate(bits))
+    }
+
+    /// Changes the setting of `dip_switch` to the next one in its table, or to the first one
+    /// after the last, and returns the description of the new setting.
+    pub fn cycle(&mut self, dip_switch: &DipSwitch) -> &'static str {
+        let value = self.value(dip_switch.key);
+        let settings = dip_switch.settings;
+        let setting = settings.iter().position(|&(_, setting, _)| setting == value);
+        let (_, value, description) =
+            settings[setting.map_or(0, |setting| setting + 1) % settings.len()];
+        self.set_value(dip_switch.key, value);
+        description
+    }
+
+    /// Returns the setting `key` as a value of the DIP switch tables of the machines.
+    fn value(&self, key: DipSwitchKey) -> u16 {
+        match key {
+            DipSwitchKey::Lives => u16::from(self.lives),
+            DipSwitchKey::ExtraLifeAt => self.extra_life_at,
+            DipSwitchKey::CoinInfo => u16::from(self.coin_info),
+        }
+    }
+
+    fn set_value(&mut self, key: DipSwitchKey, value: u16) {
+        match key {
+            DipSwitchKey::Lives => self.lives = u8::try_from(value).unwrap_or(self.lives),
+            DipSwitchKey::ExtraLifeAt => self.extra_life_at = value,
+            DipSwitchKey::CoinInfo => self.coin_info = value != 0,
+        }
+    }
+
+    fn unsupported(&self, key: DipSwitchKey) -> Error {
+        let setting = match key {
+            DipSwitchKey::Lives | DipSwitchKey::ExtraLifeAt => self.value(key).to_string(),
+            DipSwitchKey::CoinInfo => self.coin_info.to_string(),
+        };
+        Error::UnsupportedDipSwitch { key, setting }
+    }
+}
+
+/// The setting of `DipSwitches` that a DIP switch of a machine sets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DipSwitchKey {
+    /// `DipSwitches::lives`.
+    Lives,
+    /// `DipSwitches::extra_life_at`.
+    ExtraLifeAt,
+    /// `DipSwitches::coin_info`.
+    CoinInfo,
+}
+
+impl DipSwitchKey {
+    /// All the settings, in the order of the fields of `DipSwitches`.
+    pub const ALL: [DipSwitchKey; 3] =
+        [DipSwitchKey::Lives, DipSwitchKey::ExtraLifeAt, DipSwitchKey::CoinInfo];
+}
+
+impl Display for DipSwitchKey {
+    /// Formats the key as the name of the field of `DipSwitches`, as in the config file.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DipSwitchKey::Lives => "lives",
+            DipSwitchKey::ExtraLifeAt => "extra_life_at",
+            DipSwitchKey::CoinInfo => "coin_info",
+        })
+    }
+}
+
+fn bits_of(dip_switch: &DipSwitch, value: u16) -> Option<u8> {
+    dip_switch.settings.iter().find(|&&(_, setting, _)| setting == value).map(|&(bits, _, _)| bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{INVADERS, INVADPT2, MACHINES};
+
+    #[test]
+    fn all_settings() {
+        for machine in MACHINES {
+            for dip_switch in machine.dip_switches {
+                for &(bits, value, _) in dip_switch.settings {
+                    let mut dip_switches = DipSwitches::default();
+                    dip_switches.set_value(dip_switch.key, value);
+                    assert_eq!(dip_switches.value(dip_switch.key), value);
+                    let port2 = dip_switches.to_port2(machine).unwrap();
+                    assert_eq!(port2.bits() & dip_switch.mask, bits);
+                    assert_eq!(DipSwitches::from_port2(machine, port2), dip_switches);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_settings() {
+        let dip_switches = DipSwitches { lives: 5, ..DipSwitches::default() };
+        assert!(dip_switches.to_port2(&INVADERS).is_ok());
+        assert!(matches!(
+            dip_switches.to_port2(&INVADPT2),
+            Err(Error::UnsupportedDipSwitch { key: DipSwitchKey::Lives, setting }) if setting == "5"
+        ));
+        let dip_switches = DipSwitches { extra_life_at: 1000, ..DipSwitches::default() };
+        assert!(matches!(
+            dip_switches.to_port2(&INVADPT2),
+            Err(Error::UnsupportedDipSwitch { key: DipSwitchKey::ExtraLifeAt, .. })
+        ));
+        let dip_switches = DipSwitches { extra_life_at: 2000, ..DipSwitches::default() };
+        assert!(dip_switches.to_port2(&INVADERS).is_err());
+    }
+
+    #[test]
+    fn cycle() {
+        let mut dip_switches = DipSwitches::default();
+        let number_of_lives = &INVADERS.dip_switches[0];
+        for (lives, description) in [(4, "4"), (5, "5"), (6, "6"), (3, "3")] {
+            assert_eq!(dip_switches.cycle(number_of_lives), description);
+            assert_eq!(dip_switches, DipSwitches { lives, ..DipSwitches::default() });
+        }
+        let mut dip_switches = DipSwitches { lives: 5, ..DipSwitches::default() };
+        assert_eq!(dip_switches.cycle(&INVADPT2.dip_switches[0]), "3");
+        assert!(dip_switches.to_port2(&INVADPT2).is_ok());
+    }
+}
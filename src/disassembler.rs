@@ -0,0 +1,240 @@
+//! A disassembler for the Intel 8080 instruction set.
+
+use i8080::{ConditionFlags, Cpu};
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+const REGISTER_PAIRS: [&str; 4] = ["B", "D", "H", "SP"];
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU_OPERATIONS: [&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+const ALU_IMMEDIATE_OPERATIONS: [&str; 8] =
+    ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+
+/// Disassembles the instruction at `address` in `memory`, which must be 64 KiB long.
+///
+/// Returns the instruction in Intel mnemonics, and its length in bytes. Undocumented opcodes are
+/// shown as their documented equivalents marked with an asterisk.
+///
+/// # Example
+///
+/// ```
+/// let mut memory = vec![0; 0x10000];
+/// memory[..3].copy_from_slice(&[0xC3, 0xD4, 0x18]);
+/// assert_eq!(space_invaders::disassemble(&memory, 0), ("JMP $18D4".to_string(), 3));
+/// ```
+pub fn disassemble(memory: &[u8], address: u16) -> (String, u16) {
+    let opcode = memory[usize::from(address)];
+    let byte = memory[usize::from(address.wrapping_add(1))];
+    let word = u16::from_le_bytes([byte, memory[usize::from(address.wrapping_add(2))]]);
+    let x = usize::from(opcode >> 6);
+    let y = usize::from((opcode >> 3) & 0b111);
+    let z = usize::from(opcode & 0b111);
+    let rp = REGISTER_PAIRS[y >> 1];
+    let (mnemonic, len) = match (x, y, z) {
+        (0, 0, 0) => ("NOP".to_string(), 1),
+        (0, _, 0) => ("*NOP".to_string(), 1),
+        (0, _, 1) if y & 1 == 0 => (format!("LXI {rp},${word:04X}"), 3),
+        (0, _, 1) => (format!("DAD {rp}"), 1),
+        (0, 0, 2) => ("STAX B".to_string(), 1),
+        (0, 1, 2) => ("LDAX B".to_string(), 1),
+        (0, 2, 2) => ("STAX D".to_string(), 1),
+        (0, 3, 2) => ("LDAX D".to_string(), 1),
+        (0, 4, 2) => (format!("SHLD ${word:04X}"), 3),
+        (0, 5, 2) => (format!("LHLD ${word:04X}"), 3),
+        (0, 6, 2) => (format!("STA ${word:04X}"), 3),
+        (0, 7, 2) => (format!("LDA ${word:04X}"), 3),
+        (0, _, 3) if y & 1 == 0 => (format!("INX {rp}"), 1),
+        (0, _, 3) => (format!("DCX {rp}"), 1),
+        (0, _, 4) => (format!("INR {}", REGISTERS[y]), 1),
+        (0, _, 5) => (format!("DCR {}", REGISTERS[y]), 1),
+        (0, _, 6) => (format!("MVI {},${byte:02X}", REGISTERS[y]), 2),
+        (0, _, 7) => (["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"][y].to_string(), 1),
+        (1, 6, 6) => ("HLT".to_string(), 1),
+        (1, _, _) => (format!("MOV {},{}", REGISTERS[y], REGISTERS[z]), 1),
+        (2, _, _) => (format!("{} {}", ALU_OPERATIONS[y], REGISTERS[z]), 1),
+        (3, _, 0) => (format!("R{}", CONDITIONS[y]), 1),
+        (3, _, 1) => match y {
+            1 => ("RET".to_string(), 1),
+            3 => ("*RET".to_string(), 1),
+            5 => ("PCHL".to_string(), 1),
+            7 => ("SPHL".to_string(), 1),
+            _ => (format!("POP {}", ["B", "D", "H", "PSW"][y >> 1]), 1),
+        },
+        (3, _, 2) => (format!("J{} ${word:04X}", CONDITIONS[y]), 3),
+        (3, 0, 3) => (format!("JMP ${word:04X}"), 3),
+        (3, 1, 3) => (format!("*JMP ${word:04X}"), 3),
+        (3, 2, 3) => (format!("OUT ${byte:02X}"), 2),
+        (3, 3, 3) => (format!("IN ${byte:02X}"), 2),
+        (3, 4, 3) => ("XTHL".to_string(), 1),
+        (3, 5, 3) => ("XCHG".to_string(), 1),
+        (3, 6, 3) => ("DI".to_string(), 1),
+        (3, 7, 3) => ("EI".to_string(), 1),
+        (3, _, 4) => (format!("C{} ${word:04X}", CONDITIONS[y]), 3),
+        (3, _, 5) if y & 1 == 0 => (format!("PUSH {}", ["B", "D", "H", "PSW"][y >> 1]), 1),
+        (3, 1, 5) => (format!("CALL ${word:04X}"), 3),
+        (3, _, 5) => (format!("*CALL ${word:04X}"), 3),
+        (3, _, 6) => (format!("{} ${byte:02X}", ALU_IMMEDIATE_OPERATIONS[y]), 2),
+        (3, _, 7) => (format!("RST {y}"), 1),
+        _ => unreachable!(),
+    };
+    (mnemonic, len)
+}
+
+/// Returns the address of the instruction that follows the one at `address`.
+pub(crate) fn next_address(memory: &[u8], address: u16) -> u16 {
+    address.wrapping_add(disassemble(memory, address).1)
+}
+
+/// Returns `true` if the instruction at `address` is a call or a restart, which returns to the
+/// instruction that follows it.
+pub(crate) fn is_call(memory: &[u8], address: u16) -> bool {
+    let opcode = memory[usize::from(address)];
+    opcode & 0b1100_0111 == 0b1100_0100 // Ccc
+        || opcode & 0b1100_1111 == 0b1100_1101 // CALL
+        || opcode & 0b1100_0111 == 0b1100_0111 // RST
+}
+
+/// The memory that an instruction reads and writes, as ranges of addresses given by their start
+/// and length.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MemoryAccesses {
+    pub(crate) read: Option<(u16, u16)>,
+    pub(crate) write: Option<(u16, u16)>,
+}
+
+impl MemoryAccesses {
+    /// Returns `true` if the instruction reads any of the `len` bytes from `address`.
+    pub(crate) fn reads(&self, address: u16, len: u16) -> bool {
+        overlaps(self.read, address, len)
+    }
+
+    /// Returns `true` if the instruction writes any of the `len` bytes from `address`.
+    pub(crate) fn writes(&self, address: u16, len: u16) -> bool {
+        overlaps(self.write, address, len)
+    }
+}
+
+fn overlaps(range: Option<(u16, u16)>, address: u16, len: u16) -> bool {
+    range.is_some_and(|(start, range_len)| {
+        (0..range_len).any(|i| start.wrapping_add(i).wrapping_sub(address) < len)
+    })
+}
+
+/// Returns the memory that the instruction at the PC of `cpu` is going to access, including the
+/// stack accesses of calls and returns whose conditions hold.
+pub(crate) fn memory_accesses(cpu: &Cpu, memory: &[u8]) -> MemoryAccesses {
+    let opcode = memory[usize::from(cpu.pc)];
+    let word = u16::from_le_bytes([
+        memory[usize::from(cpu.pc.wrapping_add(1))],
+        memory[usize::from(cpu.pc.wrapping_add(2))],
+    ]);
+    let bc = u16::from_be_bytes([cpu.b, cpu.c]);
+    let de = u16::from_be_bytes([cpu.d, cpu.e]);
+    let hl = u16::from_be_bytes([cpu.h, cpu.l]);
+    let pop = Some((cpu.sp, 2));
+    let push = Some((cpu.sp.wrapping_sub(2), 2));
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 0b111;
+    let z = opcode & 0b111;
+    let (read, write) = match (x, y, z) {
+        (0, 6, 4 | 5) => (Some((hl, 1)), Some((hl, 1))), // INR M, DCR M
+        (0, 6, 6) => (None, Some((hl, 1))),              // MVI M
+        (0, 0, 2) => (None, Some((bc, 1))),              // STAX B
+        (0, 1, 2) => (Some((bc, 1)), None),              // LDAX B
+        (0, 2, 2) => (None, Some((de, 1))),              // STAX D
+        (0, 3, 2) => (Some((de, 1)), None),              // LDAX D
+        (0, 4, 2) => (None, Some((word, 2))),            // SHLD
+        (0, 5, 2) => (Some((word, 2)), None),            // LHLD
+        (0, 6, 2) => (None, Some((word, 1))),            // STA
+        (0, 7, 2) => (Some((word, 1)), None),            // LDA
+        (1, 6, 6) => (None, None),                       // HLT
+        (1, 6, _) => (None, Some((hl, 1))),              // MOV M,r
+        (1, _, 6) | (2, _, 6) => (Some((hl, 1)), None),  // MOV r,M and arithmetic on M
+        (3, _, 0) if condition_holds(cpu, y) => (pop, None), // Rcc
+        (3, 5 | 7, 1) => (None, None),                   // PCHL, SPHL
+        (3, _, 1) => (pop, None),                        // POP, RET
+        (3, 4, 3) => (pop, pop),                         // XTHL
+        (3, _, 4) if condition_holds(cpu, y) => (None, push), // Ccc
+        (3, _, 5) | (3, _, 7) => (None, push),           // PUSH, CALL, RST
+        _ => (None, None),
+    };
+    MemoryAccesses { read, write }
+}
+
+/// Returns `true` if the condition encoded as `y` in a conditional jump, call or return holds.
+fn condition_holds(cpu: &Cpu, y: u8) -> bool {
+    let flag =
+        [ConditionFlags::ZERO, ConditionFlags::CARRY, ConditionFlags::PARITY, ConditionFlags::SIGN]
+            [usize::from(y >> 1)];
+    cpu.condition_flags.contains(flag) == (y & 1 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use i8080::Intel8080;
+
+    use super::*;
+    use crate::tests::builder;
+
+    #[test]
+    fn disassemble_opcodes() {
+        let mut memory = vec![0; 0x10000];
+        for (bytes, expected) in [
+            (&[0x00][..], ("NOP", 1)),
+            (&[0x08], ("*NOP", 1)),
+            (&[0x31, 0x00, 0x24], ("LXI SP,$2400", 3)),
+            (&[0x7E], ("MOV A,M", 1)),
+            (&[0x76], ("HLT", 1)),
+            (&[0xC2, 0x34, 0x12], ("JNZ $1234", 3)),
+            (&[0xCB, 0x34, 0x12], ("*JMP $1234", 3)),
+            (&[0xD3, 0x06], ("OUT $06", 2)),
+            (&[0xD9], ("*RET", 1)),
+            (&[0xFE, 0x10], ("CPI $10", 2)),
+            (&[0xCF], ("RST 1", 1)),
+        ] {
+            memory[..bytes.len()].copy_from_slice(bytes);
+            assert_eq!(disassemble(&memory, 0), (expected.0.to_string(), expected.1));
+        }
+        // An instruction that wraps around the end of memory.
+        memory[0xFFFF] = 0xC3;
+        assert_eq!(disassemble(&memory, 0xFFFF), ("JMP $10CF".to_string(), 3));
+        assert_eq!(next_address(&memory, 0xFFFF), 0x0002);
+    }
+
+    #[test]
+    fn predict_memory_accesses() {
+        let mut space_invaders = builder(&[]).build().unwrap();
+        let i8080 = &mut space_invaders.i8080;
+        (i8080.cpu.h, i8080.cpu.l) = (0x20, 0x10);
+        i8080.cpu.sp = 0x2400;
+        type Range = Option<(u16, u16)>;
+        fn accesses(i8080: &mut Intel8080, bytes: &[u8]) -> (Range, Range) {
+            i8080.memory[..bytes.len()].copy_from_slice(bytes);
+            let accesses = memory_accesses(&i8080.cpu, &i8080.memory);
+            (accesses.read, accesses.write)
+        }
+        assert_eq!(accesses(i8080, &[0x32, 0x00, 0x24]), (None, Some((0x2400, 1)))); // STA $2400
+        assert_eq!(accesses(i8080, &[0x2A, 0x00, 0x24]), (Some((0x2400, 2)), None)); // LHLD $2400
+        assert_eq!(accesses(i8080, &[0x77]), (None, Some((0x2010, 1)))); // MOV M,A
+        assert_eq!(accesses(i8080, &[0x34]), (Some((0x2010, 1)), Some((0x2010, 1)))); // INR M
+        assert_eq!(accesses(i8080, &[0xCD, 0x34, 0x12]), (None, Some((0x23FE, 2)))); // CALL $1234
+        assert_eq!(accesses(i8080, &[0xC9]), (Some((0x2400, 2)), None)); // RET
+        assert_eq!(accesses(i8080, &[0xE3]), (Some((0x2400, 2)), Some((0x2400, 2)))); // XTHL
+        assert_eq!(accesses(i8080, &[0xCC, 0x34, 0x12]), (None, None)); // CZ $1234, not taken
+        assert_eq!(accesses(i8080, &[0xC8]), (None, None)); // RZ, not taken
+        i8080.cpu.condition_flags = ConditionFlags::ZERO;
+        assert_eq!(accesses(i8080, &[0xCC, 0x34, 0x12]), (None, Some((0x23FE, 2)))); // CZ $1234
+        assert_eq!(accesses(i8080, &[0xC8]), (Some((0x2400, 2)), None)); // RZ
+        assert!(!is_call(&i8080.memory, 0));
+        i8080.memory[0] = 0xCF; // RST 1
+        assert!(is_call(&i8080.memory, 0));
+    }
+
+    #[test]
+    fn overlapping_accesses() {
+        let accesses = MemoryAccesses { read: Some((0x2400, 1)), write: Some((0xFFFF, 2)) };
+        assert!(accesses.reads(0x23FF, 2));
+        assert!(!accesses.reads(0x2401, 1));
+        assert!(accesses.writes(0x0000, 1));
+        assert!(!accesses.writes(0x0001, 0xFFFE));
+    }
+}
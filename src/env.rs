@@ -0,0 +1,344 @@
+//! An environment for reinforcement learning agents that play Space Invaders.
+
+use crate::{
+    game_state::Player, machine, Error, Port1, Result, SpaceInvaders, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// An action of the player, which is held for the frames of a step.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    Noop,
+    Fire,
+    Left,
+    Right,
+    LeftFire,
+    RightFire,
+}
+
+impl Action {
+    /// All the actions, in the order of their discriminants.
+    pub const ALL: [Action; 6] = [
+        Action::Noop,
+        Action::Fire,
+        Action::Left,
+        Action::Right,
+        Action::LeftFire,
+        Action::RightFire,
+    ];
+
+    fn port1(self) -> Port1 {
+        match self {
+            Action::Noop => Port1::empty(),
+            Action::Fire => Port1::PLAYER_1_FIRE,
+            Action::Left => Port1::PLAYER_1_LEFT,
+            Action::Right => Port1::PLAYER_1_RIGHT,
+            Action::LeftFire => Port1::PLAYER_1_LEFT | Port1::PLAYER_1_FIRE,
+            Action::RightFire => Port1::PLAYER_1_RIGHT | Port1::PLAYER_1_FIRE,
+        }
+    }
+}
+
+/// How an [`Env`] returns the screen as an observation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ObservationFormat {
+    /// The 1bpp framebuffer, as [`SpaceInvaders::framebuffer`] returns it.
+    Packed,
+    /// A byte per pixel, 1 if the pixel is lit and 0 otherwise, in rows from the top of the
+    /// upright screen.
+    #[default]
+    Unpacked,
+    /// As `Unpacked`, with each pixel standing for a square of `factor` by `factor` pixels of the
+    /// screen, which is lit if any of them is. `factor` must not be 0.
+    Downsampled { factor: u8 },
+}
+
+/// The number of frames that [`Env::reset`] waits at most for a game to start.
+const START_FRAMES: u32 = 60 * 60;
+
+/// An environment in which an agent plays one-player games of Space Invaders.
+///
+/// The machine has no audio, and its interrupts are raised at fixed states of each frame, so an
+/// episode that takes the same actions always has the same observations and rewards.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs;
+/// use space_invaders::{Action, Env};
+///
+/// let mut roms = Vec::new();
+/// for name in ["invaders.h", "invaders.g", "invaders.f", "invaders.e"] {
+///     roms.push(fs::read(name)?);
+/// }
+/// let mut env = Env::new(&roms)?;
+/// env.set_frame_skip(4);
+/// let _observation = env.reset()?;
+/// let mut score = 0;
+/// loop {
+///     let (_observation, reward, done) = env.step(Action::Fire)?;
+///     score += reward;
+///     if done {
+///         break;
+///     }
+/// }
+/// println!("scored {score} points");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Env {
+    space_invaders: SpaceInvaders,
+    power_on_state: Vec<u8>,
+    // The state at the start of a game, which every episode starts from.
+    start_state: Option<Vec<u8>>,
+    frame_skip: u32,
+    observation_format: ObservationFormat,
+    score: u16,
+}
+
+impl Env {
+    /// Constructs an environment from the contents of the Space Invaders ROMs.
+    pub fn new<R: AsRef<[u8]>>(roms: &[R]) -> Result<Self> {
+        let space_invaders =
+            SpaceInvaders::from_bytes(&machine::INVADERS, roms, Default::default(), None)?;
+        Self::from_space_invaders(space_invaders)
+    }
+
+    fn from_space_invaders(space_invaders: SpaceInvaders) -> Result<Self> {
+        let mut power_on_state = Vec::new();
+        space_invaders.save_state(&mut power_on_state)?;
+        Ok(Env {
+            space_invaders,
+            power_on_state,
+            start_state: None,
+            frame_skip: 1,
+            observation_format: ObservationFormat::default(),
+            score: 0,
+        })
+    }
+
+    /// Sets the number of frames that each step runs with the same action, 1 by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_skip` is 0.
+    pub fn set_frame_skip(&mut self, frame_skip: u32) {
+        assert!(frame_skip > 0, "frame_skip must not be 0");
+        self.frame_skip = frame_skip;
+    }
+
+    /// Sets how the screen is returned as an observation, `ObservationFormat::Unpacked` by
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format is `ObservationFormat::Downsampled` with a factor of 0.
+    pub fn set_observation_format(&mut self, observation_format: ObservationFormat) {
+        if let ObservationFormat::Downsampled { factor } = observation_format {
+            assert!(factor > 0, "factor must not be 0");
+        }
+        self.observation_format = observation_format;
+    }
+
+    /// Returns the width and the height in pixels of the observations, of which a packed
+    /// observation holds 8 per byte.
+    pub fn observation_size(&self) -> (u32, u32) {
+        match self.observation_format {
+            ObservationFormat::Packed | ObservationFormat::Unpacked => {
+                (SCREEN_WIDTH, SCREEN_HEIGHT)
+            }
+            ObservationFormat::Downsampled { factor } => {
+                let factor = u32::from(factor);
+                (SCREEN_WIDTH.div_ceil(factor), SCREEN_HEIGHT.div_ceil(factor))
+            }
+        }
+    }
+
+    /// Returns the machine, for instance to look at its
+    /// [`GameState`](crate::game_state::GameState).
+    pub fn space_invaders(&self) -> &SpaceInvaders {
+        &self.space_invaders
+    }
+
+    /// Starts an episode at the start of a one-player game, and returns the first observation.
+    ///
+    /// The first reset boots the machine, inserts a coin and starts a game, which takes a few
+    /// seconds of emulated time; the others restore the state at the start of that game. Fails
+    /// with `Error::GameNotStarted` if no game has started within a minute of emulated time.
+    pub fn reset(&mut self) -> Result<Vec<u8>> {
+        match &self.start_state {
+            Some(start_state) => self.space_invaders.load_state(&start_state[..])?,
+            None => {
+                self.space_invaders.load_state(&self.power_on_state[..])?;
+                self.start_game()?;
+                let mut start_state = Vec::new();
+                self.space_invaders.save_state(&mut start_state)?;
+                self.start_state = Some(start_state);
+            }
+        }
+        self.score = self.space_invaders.game_state().score(Player::One);
+        Ok(self.observation())
+    }
+
+    /// Runs the frames of a step while holding `action`, and returns the observation, the points
+    /// scored and whether the game is over.
+    ///
+    /// The step ends early if the game ends. A step after the game is over runs no frame.
+    pub fn step(&mut self, action: Action) -> Result<(Vec<u8>, u32, bool)> {
+        let mut done = !self.space_invaders.game_state().is_playing();
+        for _ in 0..self.frame_skip {
+            if done {
+                break;
+            }
+            self.space_invaders.port1 = Port1::default() | action.port1();
+            self.space_invaders.run_frame()?;
+            done = !self.space_invaders.game_state().is_playing();
+        }
+        self.space_invaders.port1 = Port1::default();
+        let score = self.space_invaders.game_state().score(Player::One);
+        // Scores are 4 BCD digits, which wrap around from 9999 to 0.
+        let reward = (u32::from(score) + 10000 - u32::from(self.score)) % 10000;
+        self.score = score;
+        Ok((self.observation(), reward, done))
+    }
+
+    /// Inserts a coin and presses the start button of player 1 until a game is being played.
+    fn start_game(&mut self) -> Result<()> {
+        for frame in 0..START_FRAMES {
+            let game_state = self.space_invaders.game_state();
+            if game_state.is_playing() {
+                self.space_invaders.port1 = Port1::default();
+                return Ok(());
+            }
+            // The switches are held for 8 frames and released for 8, as the game acts on their
+            // transitions.
+            let input = match (frame % 16 < 8, game_state.credits()) {
+                (false, _) => Port1::empty(),
+                (true, 0) => Port1::COIN,
+                (true, _) => Port1::PLAYER_1_START,
+            };
+            self.space_invaders.port1 = Port1::default() | input;
+            self.space_invaders.run_frame()?;
+        }
+        Err(Error::GameNotStarted { frames: START_FRAMES })
+    }
+
+    fn observation(&self) -> Vec<u8> {
+        let framebuffer = self.space_invaders.framebuffer();
+        match self.observation_format {
+            ObservationFormat::Packed => framebuffer.to_vec(),
+            ObservationFormat::Unpacked => downsample(framebuffer, 1),
+            ObservationFormat::Downsampled { factor } => downsample(framebuffer, factor),
+        }
+    }
+}
+
+/// Unpacks `framebuffer` into the upright screen, with each pixel standing for a square of
+/// `factor` by `factor` pixels.
+fn downsample(framebuffer: &[u8], factor: u8) -> Vec<u8> {
+    let (width, height) = (SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize);
+    let factor = usize::from(factor);
+    let downsampled_width = width.div_ceil(factor);
+    let mut pixels = vec![0; downsampled_width * height.div_ceil(factor)];
+    // Each column of the upright screen is stored from the bottom up, 8 pixels per byte.
+    for (i, &byte) in framebuffer.iter().enumerate().filter(|(_, &byte)| byte != 0) {
+        let x = i / (height / 8);
+        for bit in (0..8).filter(|bit| byte & (1 << bit) != 0) {
+            let y = height - 1 - (i % (height / 8) * 8 + bit);
+            pixels[y / factor * downsampled_width + x / factor] = 1;
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    // MVI A,1; STA $20EF; then LDA $2400; INR A; STA $2400; JMP $0005, which starts a game at
+    // once and keeps changing the screen.
+    const PROGRAM: [u8; 15] =
+        [0x3E, 0x01, 0x32, 0xEF, 0x20, 0x3A, 0x00, 0x24, 0x3C, 0x32, 0x00, 0x24, 0xC3, 0x05, 0x00];
+
+    fn env(program: &[u8]) -> Env {
+        Env::from_space_invaders(builder(program).build().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn downsample() {
+        let mut framebuffer = vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT / 8) as usize];
+        // The bottom left, top left and top right pixels of the upright screen.
+        framebuffer[0] = 0x01;
+        framebuffer[31] = 0x80;
+        framebuffer[223 * 32 + 31] = 0x80;
+        for (factor, width, height) in [(1, 224, 256), (8, 28, 32), (3, 75, 86)] {
+            let pixels = super::downsample(&framebuffer, factor);
+            assert_eq!(pixels.len(), width * height);
+            let mut expected = vec![0; width * height];
+            expected[(height - 1) * width] = 1;
+            expected[0] = 1;
+            expected[width - 1] = 1;
+            assert_eq!(pixels, expected, "factor {factor}");
+        }
+    }
+
+    #[test]
+    fn observation_size() {
+        let mut env = env(&[]);
+        for (format, pixels_per_byte) in [
+            (ObservationFormat::Packed, 8),
+            (ObservationFormat::Unpacked, 1),
+            (ObservationFormat::Downsampled { factor: 1 }, 1),
+            (ObservationFormat::Downsampled { factor: 2 }, 1),
+            (ObservationFormat::Downsampled { factor: 3 }, 1),
+        ] {
+            env.set_observation_format(format);
+            let (width, height) = env.observation_size();
+            assert_eq!(env.observation().len() as u32, width * height / pixels_per_byte);
+        }
+    }
+
+    #[test]
+    fn reward() {
+        let mut env = env(&[0xC3, 0x00, 0x00]);
+        let memory = &mut env.space_invaders.i8080.memory;
+        memory[0x20EF] = 1;
+        memory[0x20F8..0x20FA].copy_from_slice(&[0x90, 0x99]);
+        env.score = 9990;
+        assert_eq!(env.step(Action::Noop).unwrap().1, 0);
+        // The score wraps around past 9999, from 9990 to 20.
+        env.space_invaders.i8080.memory[0x20F8..0x20FA].copy_from_slice(&[0x20, 0x00]);
+        assert_eq!(env.step(Action::Noop).unwrap().1, 30);
+    }
+
+    #[test]
+    fn step_when_done() {
+        let mut env = env(&[0xC3, 0x00, 0x00]);
+        env.set_frame_skip(4);
+        env.space_invaders.i8080.memory[0x20EF] = 1;
+        let (_, _, done) = env.step(Action::Fire).unwrap();
+        assert!(!done);
+        assert_eq!(env.space_invaders.frame_count(), 4);
+        env.space_invaders.i8080.memory[0x20EF] = 0;
+        let (_, reward, done) = env.step(Action::Fire).unwrap();
+        assert_eq!((reward, done), (0, true));
+        assert_eq!(env.space_invaders.frame_count(), 4);
+    }
+
+    #[test]
+    fn reset() {
+        let mut env = env(&PROGRAM);
+        let mut episodes = Vec::new();
+        for _ in 0..2 {
+            let mut observations = vec![env.reset().unwrap()];
+            let mut state = Vec::new();
+            env.space_invaders.save_state(&mut state).unwrap();
+            for action in [Action::Left, Action::Fire, Action::Noop] {
+                observations.push(env.step(action).unwrap().0);
+            }
+            episodes.push((state, observations));
+        }
+        assert_eq!(episodes[0], episodes[1]);
+        // The steps have changed the screen.
+        assert_ne!(episodes[0].1[0], episodes[0].1[3]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Sets of files, such as ROMs and samples, stored in a directory or in a zip archive.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use zip::{result::ZipResult, ZipArchive};
+
+use crate::{Error, Result};
+
+/// The most bytes reserved in advance for an entry of a zip archive, whose declared size may be
+/// wrong.
+const MAX_RESERVED_LEN: u64 = 0x10000;
+
+/// A set of files stored in a directory or in a zip archive, as MAME stores ROM sets and sample
+/// sets.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use space_invaders::file_set::FileSet;
+///
+/// // Opens roms/invaders.zip if it exists, or else the directory roms.
+/// let roms = FileSet::open(Path::new("roms"), "invaders")?;
+/// let invaders_h = roms.read("invaders.h")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub enum FileSet {
+    /// The files in a directory, which are read on demand.
+    Directory(PathBuf),
+    /// The files in a zip archive at `path`, which have been read into memory.
+    Zip { path: PathBuf, entries: Vec<(String, Vec<u8>)> },
+}
+
+impl FileSet {
+    /// Opens `path`, which is a zip archive, a directory that contains `{name}.zip`, or a
+    /// directory that contains the files themselves.
+    pub fn open(path: &Path, name: &str) -> Result<Self> {
+        if path.is_dir() {
+            let zip_path = path.join(format!("{name}.zip"));
+            match zip_path.is_file() {
+                true => Self::open_zip(&zip_path),
+                false => Ok(FileSet::Directory(path.to_path_buf())),
+            }
+        } else {
+            Self::open_zip(path)
+        }
+    }
+
+    fn open_zip(path: &Path) -> Result<Self> {
+        Self::read_zip(path).map_err(|source| Error::Zip { path: path.to_path_buf(), source })
+    }
+
+    fn read_zip(path: &Path) -> ZipResult<Self> {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        let mut entries = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if !file.is_file() {
+                continue;
+            }
+            // Entries are looked up by their file names, wherever they are in the archive.
+            let name = match Path::new(file.name()).file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let mut bytes = Vec::with_capacity(file.size().min(MAX_RESERVED_LEN) as usize);
+            file.read_to_end(&mut bytes)?;
+            entries.push((name, bytes));
+        }
+        Ok(FileSet::Zip { path: path.to_path_buf(), entries })
+    }
+
+    /// Returns the path of the file `name`, for use in messages. The path of a file in a zip
+    /// archive is that of the archive followed by the name.
+    pub fn path(&self, name: &str) -> PathBuf {
+        match self {
+            FileSet::Directory(path) | FileSet::Zip { path, .. } => path.join(name),
+        }
+    }
+
+    /// Reads the file `name`, or returns `None` if there is no such file.
+    pub fn read(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            FileSet::Directory(path) => match fs::read(path.join(name)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            },
+            FileSet::Zip { entries, .. } => Ok(entries
+                .iter()
+                .find(|(entry_name, _)| entry_name == name)
+                .map(|(_, bytes)| bytes.clone())),
+        }
+    }
+
+    /// Reads all the files of at most `max_len` bytes, with their names.
+    pub fn read_all(&self, max_len: u64) -> io::Result<Vec<(String, Vec<u8>)>> {
+        match self {
+            FileSet::Directory(path) => {
+                let mut files = Vec::new();
+                for entry in fs::read_dir(path)? {
+                    let entry = entry?;
+                    let metadata = entry.metadata()?;
+                    if metadata.is_file() && metadata.len() <= max_len {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        files.push((name, fs::read(entry.path())?));
+                    }
+                }
+                Ok(files)
+            }
+            FileSet::Zip { entries, .. } => Ok(entries
+                .iter()
+                .filter(|(_, bytes)| bytes.len() as u64 <= max_len)
+                .cloned()
+                .collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        io::{Cursor, Write},
+        process,
+    };
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("space-invaders-{name}-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn open_zip() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("invaders/", SimpleFileOptions::default()).unwrap();
+        writer.start_file("invaders/invaders.h", SimpleFileOptions::default()).unwrap();
+        writer.write_all(&[0xAA; 0x800]).unwrap();
+        writer.start_file("invaders.g", SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"g").unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        let dir = temp_dir("file-set");
+        let zip_path = dir.join("invaders.zip");
+        fs::write(&zip_path, zip).unwrap();
+        // The archive is opened by its path and by the directory that contains it.
+        for file_set in [FileSet::open(&zip_path, "invaders"), FileSet::open(&dir, "invaders")] {
+            let file_set = file_set.unwrap();
+            assert_eq!(file_set.read("invaders.h").unwrap(), Some(vec![0xAA; 0x800]));
+            assert_eq!(file_set.read("invaders.g").unwrap(), Some(b"g".to_vec()));
+            assert_eq!(file_set.read("invaders.f").unwrap(), None);
+            assert_eq!(file_set.path("invaders.h"), zip_path.join("invaders.h"));
+            assert_eq!(file_set.read_all(1).unwrap(), [("invaders.g".to_string(), b"g".to_vec())]);
+        }
+        // A directory without the archive holds the files themselves.
+        assert!(matches!(
+            FileSet::open(&dir, "invadpt2").unwrap(),
+            FileSet::Directory(path) if path == dir
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open_errors() {
+        let dir = temp_dir("file-set-errors");
+        let not_zip_path = dir.join("invaders.zip");
+        fs::write(&not_zip_path, b"not a zip archive").unwrap();
+        for path in [dir.join("missing.zip"), not_zip_path] {
+            match FileSet::open(&path, "invaders") {
+                Err(err @ Error::Zip { .. }) => {
+                    assert!(err.to_string().starts_with(&format!("{}: ", path.display())));
+                }
+                result => panic!("unexpected result: {result:?}"),
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
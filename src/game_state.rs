@@ -0,0 +1,301 @@
+//! A view of the state of a game of Space Invaders in RAM.
+//!
+//! The addresses are those of the disassembly of the Space Invaders ROMs:
+//!
+//! | Address         | Contents                                                       |
+//! | --------------- | -------------------------------------------------------------- |
+//! | 0x201B          | The x coordinate of the player's cannon                        |
+//! | 0x2025          | The status of the player's shot, 0 when none has been fired    |
+//! | 0x2029-0x202A   | The y and x coordinates of the player's shot                   |
+//! | 0x2035          | The status of the rolling shot, 0 when inactive                |
+//! | 0x203D-0x203E   | The y and x coordinates of the rolling shot                    |
+//! | 0x2045          | The status of the plunger shot, 0 when inactive                |
+//! | 0x204D-0x204E   | The y and x coordinates of the plunger shot                    |
+//! | 0x2055          | The status of the squiggly shot, 0 when inactive               |
+//! | 0x205D-0x205E   | The y and x coordinates of the squiggly shot                   |
+//! | 0x2067          | The high byte of the data of the current player, 0x21 or 0x22  |
+//! | 0x2084          | Whether the UFO is on the screen                               |
+//! | 0x2087          | The x coordinate of the UFO                                    |
+//! | 0x20EB          | The number of credits, in BCD                                  |
+//! | 0x20EF          | Whether a game is being played, rather than the attract mode   |
+//! | 0x20F4-0x20F5   | The high score, in BCD, low byte first                         |
+//! | 0x20F8-0x20F9   | The score of player 1, in BCD, low byte first                  |
+//! | 0x20FC-0x20FD   | The score of player 2, in BCD, low byte first                  |
+//! | 0x2100-0x2136   | Player 1's invaders, 1 if alive, by rows of 11 from the bottom |
+//! | 0x21FE          | Player 1's wave, from 0                                        |
+//! | 0x21FF          | Player 1's lives                                               |
+//! | 0x2200-0x2236   | Player 2's invaders                                            |
+//! | 0x22FE          | Player 2's wave, from 0                                        |
+//! | 0x22FF          | Player 2's lives                                               |
+
+/// A player of a two-player game.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Player {
+    One,
+    Two,
+}
+
+impl Player {
+    /// Returns the address of the data of the player, 0x2100 or 0x2200.
+    fn data(self) -> usize {
+        match self {
+            Player::One => 0x2100,
+            Player::Two => 0x2200,
+        }
+    }
+}
+
+/// A shot, as returned by [`GameState::shots`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shot {
+    /// The status that the game keeps for the shot, which is not 0 while the shot is active.
+    pub status: u8,
+    /// The x coordinate of the shot.
+    pub x: u8,
+    /// The y coordinate of the shot.
+    pub y: u8,
+}
+
+impl Shot {
+    /// Returns `true` if the shot is on the screen or blowing up.
+    pub fn is_active(&self) -> bool {
+        self.status != 0
+    }
+}
+
+/// The shots on the screen, as returned by [`GameState::shots`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shots {
+    /// The shot of the player's cannon.
+    pub player: Shot,
+    /// The invaders' shot that aims at the player.
+    pub rolling: Shot,
+    /// The invaders' shot that follows a fixed sequence of columns.
+    pub plunger: Shot,
+    /// The invaders' other shot that follows a fixed sequence of columns.
+    pub squiggly: Shot,
+}
+
+/// The number of rows of invaders.
+pub const INVADER_ROWS: usize = 5;
+/// The number of columns of invaders.
+pub const INVADER_COLUMNS: usize = 11;
+
+/// A view of the state of a game of Space Invaders, as returned by
+/// [`SpaceInvaders::game_state`](crate::SpaceInvaders::game_state).
+///
+/// The view decodes the RAM layout of Space Invaders. The other boards of the family lay out
+/// their RAM differently.
+///
+/// # Example
+///
+/// ```no_run
+/// use space_invaders::{game_state::Player, SpaceInvaders};
+///
+/// let mut space_invaders = SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?;
+/// space_invaders.run_frame()?;
+/// let game_state = space_invaders.game_state();
+/// println!("score {} lives {}", game_state.score(Player::One), game_state.lives(Player::One));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct GameState<'a> {
+    memory: &'a [u8],
+}
+
+impl<'a> GameState<'a> {
+    /// Returns a view of `memory`, the 64 KiB address space of the CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` does not cover the RAM at 0x2000-0x23FF.
+    pub fn new(memory: &'a [u8]) -> Self {
+        assert!(memory.len() >= 0x2400, "memory does not cover the RAM");
+        GameState { memory }
+    }
+
+    /// Returns the score of `player`.
+    pub fn score(&self, player: Player) -> u16 {
+        match player {
+            Player::One => self.bcd_word(0x20F8),
+            Player::Two => self.bcd_word(0x20FC),
+        }
+    }
+
+    /// Returns the high score.
+    pub fn high_score(&self) -> u16 {
+        self.bcd_word(0x20F4)
+    }
+
+    /// Returns the number of credits.
+    pub fn credits(&self) -> u8 {
+        bcd(self.memory[0x20EB])
+    }
+
+    /// Returns `true` while a game is being played, and `false` in the attract mode.
+    pub fn is_playing(&self) -> bool {
+        self.memory[0x20EF] != 0
+    }
+
+    /// Returns the player whose turn it is.
+    pub fn current_player(&self) -> Player {
+        match self.memory[0x2067] {
+            0x22 => Player::Two,
+            _ => Player::One,
+        }
+    }
+
+    /// Returns the number of lives of `player`.
+    pub fn lives(&self, player: Player) -> u8 {
+        self.memory[player.data() + 0xFF]
+    }
+
+    /// Returns the wave that `player` is playing, from 1.
+    pub fn wave(&self, player: Player) -> u8 {
+        self.memory[player.data() + 0xFE].wrapping_add(1)
+    }
+
+    /// Returns which invaders of `player` are alive, by rows from the bottom and by columns from
+    /// the left.
+    pub fn invaders(&self, player: Player) -> [[bool; INVADER_COLUMNS]; INVADER_ROWS] {
+        let mut invaders = [[false; INVADER_COLUMNS]; INVADER_ROWS];
+        let flags = &self.memory[player.data()..player.data() + INVADER_ROWS * INVADER_COLUMNS];
+        for (row, flags) in invaders.iter_mut().zip(flags.chunks(INVADER_COLUMNS)) {
+            for (alive, &flag) in row.iter_mut().zip(flags) {
+                *alive = flag != 0;
+            }
+        }
+        invaders
+    }
+
+    /// Returns the x coordinate of the player's cannon.
+    pub fn player_x(&self) -> u8 {
+        self.memory[0x201B]
+    }
+
+    /// Returns the x coordinate of the UFO, or `None` if the UFO is not on the screen.
+    pub fn ufo_x(&self) -> Option<u8> {
+        match self.memory[0x2084] {
+            0 => None,
+            _ => Some(self.memory[0x2087]),
+        }
+    }
+
+    /// Returns the shots of the player and of the invaders, whether active or not.
+    pub fn shots(&self) -> Shots {
+        Shots {
+            player: self.shot(0x2025, 0x2029),
+            rolling: self.shot(0x2035, 0x203D),
+            plunger: self.shot(0x2045, 0x204D),
+            squiggly: self.shot(0x2055, 0x205D),
+        }
+    }
+
+    fn shot(&self, status: usize, coordinates: usize) -> Shot {
+        Shot {
+            status: self.memory[status],
+            x: self.memory[coordinates + 1],
+            y: self.memory[coordinates],
+        }
+    }
+
+    fn bcd_word(&self, address: usize) -> u16 {
+        u16::from(bcd(self.memory[address + 1])) * 100 + u16::from(bcd(self.memory[address]))
+    }
+}
+
+/// Decodes a byte of two BCD digits.
+fn bcd(byte: u8) -> u8 {
+    (byte >> 4) * 10 + (byte & 0x0F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores() {
+        let mut memory = vec![0; 0x10000];
+        memory[0x20F4..0x20F6].copy_from_slice(&[0x90, 0x12]);
+        memory[0x20F8..0x20FA].copy_from_slice(&[0x50, 0x03]);
+        memory[0x20FC..0x20FE].copy_from_slice(&[0x99, 0x99]);
+        memory[0x20EB] = 0x15;
+        let game_state = GameState::new(&memory);
+        assert_eq!(game_state.high_score(), 1290);
+        assert_eq!(game_state.score(Player::One), 350);
+        assert_eq!(game_state.score(Player::Two), 9999);
+        assert_eq!(game_state.credits(), 15);
+    }
+
+    #[test]
+    fn players() {
+        let mut memory = vec![0; 0x10000];
+        memory[0x20EF] = 1;
+        memory[0x21FE] = 0;
+        memory[0x21FF] = 3;
+        memory[0x22FE] = 4;
+        memory[0x22FF] = 1;
+        for (data, player) in [(0x21, Player::One), (0x22, Player::Two)] {
+            memory[0x2067] = data;
+            let game_state = GameState::new(&memory);
+            assert!(game_state.is_playing());
+            assert_eq!(game_state.current_player(), player);
+            assert_eq!(game_state.wave(Player::One), 1);
+            assert_eq!(game_state.lives(Player::One), 3);
+            assert_eq!(game_state.wave(Player::Two), 5);
+            assert_eq!(game_state.lives(Player::Two), 1);
+        }
+    }
+
+    #[test]
+    fn invaders() {
+        let mut memory = vec![0; 0x10000];
+        // Player 1 has only the leftmost invader of the bottom row and the rightmost invader of
+        // the second row, and player 2 only the leftmost invader of the top row.
+        memory[0x2100] = 1;
+        memory[0x2100 + INVADER_COLUMNS + 10] = 1;
+        memory[0x2200 + (INVADER_ROWS - 1) * INVADER_COLUMNS] = 1;
+        let game_state = GameState::new(&memory);
+        let mut invaders = [[false; INVADER_COLUMNS]; INVADER_ROWS];
+        invaders[0][0] = true;
+        invaders[1][10] = true;
+        assert_eq!(game_state.invaders(Player::One), invaders);
+        let mut invaders = [[false; INVADER_COLUMNS]; INVADER_ROWS];
+        invaders[INVADER_ROWS - 1][0] = true;
+        assert_eq!(game_state.invaders(Player::Two), invaders);
+    }
+
+    #[test]
+    fn ufo() {
+        let mut memory = vec![0; 0x10000];
+        memory[0x2087] = 0x68;
+        assert_eq!(GameState::new(&memory).ufo_x(), None);
+        memory[0x2084] = 1;
+        assert_eq!(GameState::new(&memory).ufo_x(), Some(0x68));
+    }
+
+    #[test]
+    fn shots() {
+        let mut memory = vec![0; 0x10000];
+        memory[0x201B] = 0x30;
+        memory[0x2025] = 1;
+        memory[0x2029..0x202B].copy_from_slice(&[0x40, 0x38]);
+        memory[0x2035] = 0x80;
+        memory[0x203D..0x203F].copy_from_slice(&[0xA0, 0x50]);
+        memory[0x204D..0x204F].copy_from_slice(&[0xB0, 0x60]);
+        memory[0x2055] = 0x04;
+        memory[0x205D..0x205F].copy_from_slice(&[0xC0, 0x70]);
+        let game_state = GameState::new(&memory);
+        assert_eq!(game_state.player_x(), 0x30);
+        let shots = game_state.shots();
+        assert_eq!(shots.player, Shot { status: 1, x: 0x38, y: 0x40 });
+        assert_eq!(shots.rolling, Shot { status: 0x80, x: 0x50, y: 0xA0 });
+        assert_eq!(shots.plunger, Shot { status: 0, x: 0x60, y: 0xB0 });
+        assert_eq!(shots.squiggly, Shot { status: 0x04, x: 0x70, y: 0xC0 });
+        assert!(shots.player.is_active() && !shots.plunger.is_active());
+    }
+}
@@ -0,0 +1,582 @@
+//! A stub for the GDB remote serial protocol.
+//!
+//! GDB has no Intel 8080 target, so the stub exposes the registers as six 16-bit registers in the
+//! order AF, BC, DE, HL, SP and PC, which are the first six registers of GDB's Z80 target. The
+//! stub supports reading and writing registers and memory, software and hardware breakpoints,
+//! write, read and access watchpoints, single-stepping, continuing, and interrupting with Ctrl-C.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    str,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use i8080::ConditionFlags;
+
+use crate::{Error, SpaceInvaders};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 7;
+
+/// A GDB remote serial protocol stub serving a connection from a debugger.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, sync::Mutex};
+/// use space_invaders::{gdb::GdbStub, SpaceInvaders};
+///
+/// let space_invaders = Mutex::new(SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?);
+/// let listener = TcpListener::bind("127.0.0.1:1234")?;
+/// let (stream, _) = listener.accept()?;
+/// GdbStub::new(stream)?.serve(&space_invaders)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    no_ack_mode: bool,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Watchpoint {
+    kind: WatchpointKind,
+    address: u16,
+    len: u16,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum WatchpointKind {
+    Write,
+    Read,
+    Access,
+}
+
+enum Point {
+    Breakpoint(u16),
+    Watchpoint(Watchpoint),
+    Unsupported,
+}
+
+enum StopReason {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint(WatchpointKind, u16),
+}
+
+impl GdbStub {
+    /// Constructs a new `GdbStub` that serves a debugger connected through `stream`.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // A stream accepted from a nonblocking listener is nonblocking on some platforms.
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            no_ack_mode: false,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    /// Serves the debugger until it detaches, kills the target or disconnects.
+    ///
+    /// `space_invaders` is stopped while the debugger is attached, except when the debugger
+    /// continues it, in which case it runs in real time until a breakpoint or a watchpoint is hit
+    /// or the debugger interrupts it. It is locked only while it is being accessed or run.
+    pub fn serve(&mut self, space_invaders: &Mutex<SpaceInvaders>) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.first() {
+                Some(b'?') => stop_reply(StopReason::Signal(SIGTRAP)),
+                Some(b'g') => read_registers(&space_invaders.lock().unwrap()),
+                Some(b'G') => write_registers(&mut space_invaders.lock().unwrap(), &packet[1..]),
+                Some(b'p') => read_register(&space_invaders.lock().unwrap(), &packet[1..]),
+                Some(b'P') => write_register(&mut space_invaders.lock().unwrap(), &packet[1..]),
+                Some(b'm') => read_memory(&space_invaders.lock().unwrap(), &packet[1..]),
+                Some(b'M') => write_memory(&mut space_invaders.lock().unwrap(), &packet[1..]),
+                Some(b'Z') => self.insert_point(&packet[1..]),
+                Some(b'z') => self.remove_point(&packet[1..]),
+                Some(b's') => {
+                    let mut space_invaders = space_invaders.lock().unwrap();
+                    match resume_address(&packet[1..]) {
+                        Some(address) => {
+                            if let Some(address) = address {
+                                space_invaders.i8080.cpu.pc = address;
+                            }
+                            let stop_reason = self.step(&mut space_invaders);
+                            stop_reply(stop_reason.unwrap_or(StopReason::Signal(SIGTRAP)))
+                        }
+                        None => "E01".to_string(),
+                    }
+                }
+                Some(b'c') => match resume_address(&packet[1..]) {
+                    Some(address) => {
+                        if let Some(address) = address {
+                            space_invaders.lock().unwrap().i8080.cpu.pc = address;
+                        }
+                        match self.run(space_invaders)? {
+                            Some(stop_reason) => stop_reply(stop_reason),
+                            None => return Ok(()),
+                        }
+                    }
+                    None => "E01".to_string(),
+                },
+                Some(b'H') => "OK".to_string(),
+                Some(b'D') => {
+                    self.write_packet("OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                _ if packet.starts_with(b"qSupported") => {
+                    "PacketSize=1000;QStartNoAckMode+;swbreak+;hwbreak+".to_string()
+                }
+                _ if packet == b"QStartNoAckMode" => {
+                    self.write_packet("OK")?;
+                    self.no_ack_mode = true;
+                    continue;
+                }
+                _ if packet == b"qAttached" => "1".to_string(),
+                // An interrupt while stopped has nothing to interrupt.
+                Some(0x03) => continue,
+                _ => String::new(), // unsupported
+            };
+            self.write_packet(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `space_invaders` frame by frame in real time until it stops.
+    ///
+    /// Returns `None` if the debugger has disconnected.
+    fn run(&mut self, space_invaders: &Mutex<SpaceInvaders>) -> io::Result<Option<StopReason>> {
+        const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+        let mut deadline = Instant::now();
+        // The instruction at the resumed PC is executed even if there is a breakpoint on it.
+        let mut resumed = true;
+        loop {
+            self.reader.get_ref().set_nonblocking(true)?;
+            let interrupted = self.poll_interrupt();
+            self.reader.get_ref().set_nonblocking(false)?;
+            match interrupted? {
+                Some(true) => return Ok(Some(StopReason::Signal(SIGINT))),
+                Some(false) => (),
+                None => return Ok(None),
+            }
+            {
+                let mut space_invaders = space_invaders.lock().unwrap();
+                let frame_count = space_invaders.frame_count();
+                while space_invaders.frame_count() == frame_count {
+                    if !resumed && self.breakpoints.contains(&space_invaders.i8080.cpu.pc) {
+                        return Ok(Some(StopReason::Breakpoint));
+                    }
+                    resumed = false;
+                    if let Some(stop_reason) = self.step(&mut space_invaders) {
+                        return Ok(Some(stop_reason));
+                    }
+                }
+            }
+            deadline += FRAME;
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    /// Executes an instruction or handles an interrupt, and returns the watchpoint hit or the
+    /// signal for the error that occurred, if any.
+    fn step(&self, space_invaders: &mut SpaceInvaders) -> Option<StopReason> {
+        let accesses = space_invaders.next_memory_accesses();
+        if let Err(err) = space_invaders.update() {
+            warn!("{err}");
+            return Some(StopReason::Signal(signal(&err)));
+        }
+        self.watchpoints.iter().find_map(|watchpoint| {
+            let Watchpoint { kind, address, len } = *watchpoint;
+            let hit = match kind {
+                WatchpointKind::Write => accesses.writes(address, len),
+                WatchpointKind::Read => accesses.reads(address, len),
+                WatchpointKind::Access => {
+                    accesses.reads(address, len) || accesses.writes(address, len)
+                }
+            };
+            hit.then_some(StopReason::Watchpoint(kind, address))
+        })
+    }
+
+    /// Returns whether the debugger has sent an interrupt, or `None` if it has disconnected.
+    ///
+    /// The stream must be in nonblocking mode.
+    fn poll_interrupt(&mut self) -> io::Result<Option<bool>> {
+        let mut byte = [0];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) if byte[0] == 0x03 => return Ok(Some(true)),
+                Ok(_) => (), // an acknowledgment
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Some(false)),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn insert_point(&mut self, args: &[u8]) -> String {
+        match parse_point(args) {
+            Some(Point::Breakpoint(address)) => {
+                self.breakpoints.insert(address);
+            }
+            Some(Point::Watchpoint(watchpoint)) => {
+                if !self.watchpoints.contains(&watchpoint) {
+                    self.watchpoints.push(watchpoint);
+                }
+            }
+            Some(Point::Unsupported) => return String::new(),
+            None => return "E01".to_string(),
+        }
+        "OK".to_string()
+    }
+
+    fn remove_point(&mut self, args: &[u8]) -> String {
+        match parse_point(args) {
+            Some(Point::Breakpoint(address)) => {
+                self.breakpoints.remove(&address);
+            }
+            Some(Point::Watchpoint(watchpoint)) => self.watchpoints.retain(|w| *w != watchpoint),
+            Some(Point::Unsupported) => return String::new(),
+            None => return "E01".to_string(),
+        }
+        "OK".to_string()
+    }
+
+    /// Reads a packet, acknowledging it unless in no-acknowledgment mode.
+    ///
+    /// An interrupt is returned as a packet consisting of the byte 0x03. Returns `None` if the
+    /// debugger has disconnected.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let mut byte = [0];
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                match byte[0] {
+                    b'$' => break,
+                    0x03 => return Ok(Some(vec![0x03])),
+                    _ => (), // an acknowledgment or noise
+                }
+            }
+            let mut packet = Vec::new();
+            if self.reader.read_until(b'#', &mut packet)? == 0 || packet.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            if self.no_ack_mode {
+                return Ok(Some(packet));
+            }
+            let expected =
+                str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if expected == Some(checksum_of(&packet)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(packet));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.writer, "${data}#{:02x}", checksum_of(data.as_bytes()))?;
+        self.writer.flush()
+    }
+}
+
+/// Returns the signal that stops the target on `err`.
+fn signal(err: &Error) -> u8 {
+    match err {
+        Error::UnknownPort { .. } | Error::UnknownPortBits { .. } => SIGBUS,
+        _ => SIGILL,
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |checksum, byte| checksum.wrapping_add(*byte))
+}
+
+fn stop_reply(stop_reason: StopReason) -> String {
+    match stop_reason {
+        StopReason::Signal(signal) => format!("S{signal:02x}"),
+        StopReason::Breakpoint => format!("T{SIGTRAP:02x}swbreak:;"),
+        StopReason::Watchpoint(kind, address) => {
+            let kind = match kind {
+                WatchpointKind::Write => "watch",
+                WatchpointKind::Read => "rwatch",
+                WatchpointKind::Access => "awatch",
+            };
+            format!("T{SIGTRAP:02x}{kind}:{address:04x};")
+        }
+    }
+}
+
+fn registers(space_invaders: &SpaceInvaders) -> [u16; 6] {
+    let cpu = &space_invaders.i8080.cpu;
+    [
+        u16::from_be_bytes([cpu.a, cpu.condition_flags.bits()]),
+        u16::from_be_bytes([cpu.b, cpu.c]),
+        u16::from_be_bytes([cpu.d, cpu.e]),
+        u16::from_be_bytes([cpu.h, cpu.l]),
+        cpu.sp,
+        cpu.pc,
+    ]
+}
+
+fn set_register(space_invaders: &mut SpaceInvaders, register: usize, value: u16) {
+    let cpu = &mut space_invaders.i8080.cpu;
+    let [high, low] = value.to_be_bytes();
+    match register {
+        0 => (cpu.a, cpu.condition_flags) = (high, ConditionFlags::from_bits_truncate(low)),
+        1 => (cpu.b, cpu.c) = (high, low),
+        2 => (cpu.d, cpu.e) = (high, low),
+        3 => (cpu.h, cpu.l) = (high, low),
+        4 => cpu.sp = value,
+        5 => cpu.pc = value,
+        _ => unreachable!(),
+    }
+}
+
+fn read_registers(space_invaders: &SpaceInvaders) -> String {
+    registers(space_invaders).iter().map(|register| encode_hex(&register.to_le_bytes())).collect()
+}
+
+fn write_registers(space_invaders: &mut SpaceInvaders, args: &[u8]) -> String {
+    match decode_hex(args) {
+        Some(bytes) if bytes.len() == 12 => {
+            for (register, value) in bytes.chunks(2).enumerate() {
+                set_register(space_invaders, register, u16::from_le_bytes([value[0], value[1]]));
+            }
+            "OK".to_string()
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+fn read_register(space_invaders: &SpaceInvaders, args: &[u8]) -> String {
+    match parse_hex(args) {
+        Some(register @ 0..=5) => {
+            encode_hex(&registers(space_invaders)[register as usize].to_le_bytes())
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+fn write_register(space_invaders: &mut SpaceInvaders, args: &[u8]) -> String {
+    let mut args = args.splitn(2, |&byte| byte == b'=');
+    match (args.next().and_then(parse_hex), args.next().and_then(decode_hex)) {
+        (Some(register @ 0..=5), Some(value)) if value.len() == 2 => {
+            set_register(
+                space_invaders,
+                register as usize,
+                u16::from_le_bytes([value[0], value[1]]),
+            );
+            "OK".to_string()
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+fn read_memory(space_invaders: &SpaceInvaders, args: &[u8]) -> String {
+    let mut args = args.splitn(2, |&byte| byte == b',');
+    match (args.next().and_then(parse_hex), args.next().and_then(parse_hex)) {
+        (Some(address), Some(len)) if address <= 0xFFFF => {
+            let bytes = (0..len.min(0x10000))
+                .map(|i| space_invaders.i8080.memory[(address as usize + i as usize) & 0xFFFF])
+                .collect::<Vec<_>>();
+            encode_hex(&bytes)
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+fn write_memory(space_invaders: &mut SpaceInvaders, args: &[u8]) -> String {
+    let mut args = args.splitn(2, |&byte| byte == b':');
+    let mut location = args.next().unwrap_or_default().splitn(2, |&byte| byte == b',');
+    match (
+        location.next().and_then(parse_hex),
+        location.next().and_then(parse_hex),
+        args.next().and_then(decode_hex),
+    ) {
+        (Some(address), Some(len), Some(bytes))
+            if address <= 0xFFFF && len as usize == bytes.len() =>
+        {
+            for (i, byte) in bytes.into_iter().enumerate() {
+                space_invaders.i8080.memory[(address as usize + i) & 0xFFFF] = byte;
+            }
+            "OK".to_string()
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+/// Parses the arguments of a `Z` or `z` packet, which are the type, the address and the kind,
+/// which is the length for watchpoints.
+fn parse_point(args: &[u8]) -> Option<Point> {
+    let mut args = args.split(|&byte| byte == b',');
+    let point_type = parse_hex(args.next()?)?;
+    let address = u16::try_from(parse_hex(args.next()?)?).ok()?;
+    let len = u16::try_from(parse_hex(args.next()?)?).ok()?.max(1);
+    let kind = match point_type {
+        0 | 1 => return Some(Point::Breakpoint(address)),
+        2 => WatchpointKind::Write,
+        3 => WatchpointKind::Read,
+        4 => WatchpointKind::Access,
+        _ => return Some(Point::Unsupported),
+    };
+    Some(Point::Watchpoint(Watchpoint { kind, address, len }))
+}
+
+/// Parses the optional address from which a `c` or `s` packet resumes.
+fn resume_address(args: &[u8]) -> Option<Option<u16>> {
+    match args {
+        [] => Some(None),
+        args => Some(Some(u16::try_from(parse_hex(args)?).ok()?)),
+    }
+}
+
+fn parse_hex(hex: &[u8]) -> Option<u32> {
+    u32::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    let digits = hex.chunks_exact(2);
+    if !digits.remainder().is_empty() {
+        return None;
+    }
+    digits.map(|digits| u8::from_str_radix(str::from_utf8(digits).ok()?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::{tests::builder, PortDirection};
+
+    /// A debugger connected to a stub.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(address: std::net::SocketAddr) -> Self {
+            let stream = TcpStream::connect(address).unwrap();
+            Self { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream }
+        }
+
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        /// Sends `packet`, checks that it is acknowledged, and returns the reply, acknowledging
+        /// it after checking its checksum.
+        fn request(&mut self, packet: &str) -> String {
+            write!(self.writer, "${packet}#{:02x}", checksum_of(packet.as_bytes())).unwrap();
+            assert_eq!(self.read_byte(), b'+', "acknowledgment of {packet}");
+            assert_eq!(self.read_byte(), b'$');
+            let mut reply = Vec::new();
+            self.reader.read_until(b'#', &mut reply).unwrap();
+            assert_eq!(reply.pop(), Some(b'#'));
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum).unwrap();
+            assert_eq!(parse_hex(&checksum), Some(u32::from(checksum_of(&reply))));
+            self.writer.write_all(b"+").unwrap();
+            String::from_utf8(reply).unwrap()
+        }
+    }
+
+    #[test]
+    fn serve() {
+        // MVI A,$55; STA $2400; NOP; JMP $0005; LDA $2000
+        let program = [0x3E, 0x55, 0x32, 0x00, 0x24, 0x00, 0xC3, 0x05, 0x00, 0x3A, 0x00, 0x20];
+        let space_invaders = Mutex::new(builder(&program).build().unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::scope(|scope| {
+            let stub = scope.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                GdbStub::new(stream).unwrap().serve(&space_invaders)
+            });
+            let mut client = Client::connect(address);
+            assert_eq!(client.request("?"), "S05");
+
+            // A = 0x12, BC = 0x3456, DE = 0x789A, HL = 0xBCDE, SP = 0x2400 and PC = 0x0000.
+            let registers = "001256349a78debc00240000";
+            assert_eq!(client.request(&format!("G{registers}")), "OK");
+            assert_eq!(client.request("g"), registers);
+            assert_eq!(client.request("p4"), "0024");
+            assert_eq!(client.request("G00"), "E01");
+
+            assert_eq!(client.request("m0000,3"), "3e5532");
+            assert_eq!(client.request("M2000,2:abcd"), "OK");
+            assert_eq!(client.request("m2000,2"), "abcd");
+            assert_eq!(client.request("M2000,3:abcd"), "E01");
+
+            // A corrupted packet is rejected and has to be sent again.
+            client.writer.write_all(b"$g#00").unwrap();
+            assert_eq!(client.read_byte(), b'-');
+
+            assert_eq!(client.request("Z0,6,1"), "OK");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p5"), "0600");
+            assert_eq!(client.request("m2400,1"), "55");
+            assert_eq!(client.request("z0,6,1"), "OK");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("p5"), "0500");
+
+            assert_eq!(client.request("Z2,2400,1"), "OK");
+            assert_eq!(client.request("s0"), "S05");
+            assert_eq!(client.request("s"), "T05watch:2400;");
+            assert_eq!(client.request("z2,2400,1"), "OK");
+            assert_eq!(client.request("Z3,2000,2"), "OK");
+            assert_eq!(client.request("s9"), "T05rwatch:2000;");
+            assert_eq!(client.request("z3,2000,2"), "OK");
+            assert_eq!(client.request("Z4,2400,1"), "OK");
+            assert_eq!(client.request("s2"), "T05awatch:2400;");
+            assert_eq!(client.request("z4,2400,1"), "OK");
+
+            // OUT $07, which is not mapped, and OUT $05 with bit 7, which is not mapped either.
+            assert_eq!(client.request("M2010,6:d3073e80d305"), "OK");
+            assert_eq!(client.request("s2010"), "S07");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("s"), "S07");
+
+            assert_eq!(client.request("D"), "OK");
+            stub.join().unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn signal_of_error() {
+        let unknown_port = Error::UnknownPort { port: 7, direction: PortDirection::Out, pc: 0 };
+        assert_eq!(signal(&unknown_port), SIGBUS);
+        assert_eq!(signal(&Error::UnknownPortBits { port: 5, bits: 0x80, pc: 0 }), SIGBUS);
+        assert_eq!(signal(&Error::InvalidRoms { reason: "" }), SIGILL);
+    }
+}
@@ -2,20 +2,49 @@
 
 use std::{
     fmt::{self, Display, Formatter},
-    fs,
-    io::{self, Cursor},
-    path::Path,
-    sync::mpsc::{Receiver, TryRecvError},
+    fs, io, mem,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use bitflags::bitflags;
 
 use log::warn;
 
-use rodio::{Decoder, OutputStreamHandle, Sink, Source};
+use rodio::OutputStreamHandle;
 
 use i8080::Intel8080;
 
+pub mod audio;
+mod builder;
+pub mod debugger;
+mod dip_switches;
+mod disassembler;
+mod env;
+pub mod file_set;
+pub mod game_state;
+pub mod gdb;
+pub mod machine;
+mod memory_map;
+mod movie;
+mod rewind;
+pub mod rom_set;
+pub mod sample_set;
+mod save_state;
+mod synth;
+
+use audio::{AudioBackend, SoundEvent, Volume};
+pub use builder::Builder;
+pub use dip_switches::{DipSwitchKey, DipSwitches};
+pub use disassembler::disassemble;
+use disassembler::MemoryAccesses;
+pub use env::{Action, Env, ObservationFormat};
+use game_state::GameState;
+use machine::{Machine, SoundId};
+use memory_map::MemoryMap;
+pub use movie::{MoviePlayer, MovieRecorder};
+pub use rewind::RewindBuffer;
+
 /// An error that can occur in this crate.
 #[derive(Debug)]
 pub enum Error {
@@ -23,6 +52,33 @@ pub enum Error {
     I8080 { source: i8080::Error },
     /// An I/O error.
     Io { source: io::Error },
+    /// A save state that cannot be loaded.
+    InvalidSaveState { reason: &'static str },
+    /// A movie that cannot be recorded or played back.
+    InvalidMovie { reason: &'static str },
+    /// A movie whose playback diverged from the recording at frame `frame`.
+    MovieDesync { frame: u64 },
+    /// An access to a port that is not mapped on the board, by the instruction at `pc`.
+    UnknownPort { port: u8, direction: PortDirection, pc: u16 },
+    /// Bits written to an output port that are not mapped on the board, by the instruction at
+    /// `pc`.
+    UnknownPortBits { port: u8, bits: u8, pc: u16 },
+    /// An opcode at `pc` that the CPU cannot execute.
+    InvalidOpcode { opcode: u8, pc: u16, source: i8080::Error },
+    /// ROMs that do not fit the machine.
+    InvalidRoms { reason: &'static str },
+    /// A high score that cannot be restored.
+    InvalidHighScore { reason: &'static str },
+    /// A game that has not started within `frames` frames of coins and start buttons.
+    GameNotStarted { frames: u32 },
+    /// A setting of the DIP switch `key`, such as `lives`, that the machine does not support.
+    UnsupportedDipSwitch { key: DipSwitchKey, setting: String },
+    /// A sample manifest that cannot be used.
+    InvalidSampleManifest { reason: &'static str },
+    /// An error from crate `toml`.
+    Toml { source: toml::de::Error },
+    /// An error from crate `zip` in opening or reading the archive at `path`.
+    Zip { path: PathBuf, source: zip::result::ZipError },
 }
 
 impl Display for Error {
@@ -30,6 +86,31 @@ impl Display for Error {
         match self {
             Error::I8080 { source } => source.fmt(f),
             Error::Io { source } => source.fmt(f),
+            Error::InvalidSaveState { reason } => write!(f, "Invalid save state: {reason}"),
+            Error::InvalidMovie { reason } => write!(f, "Invalid movie: {reason}"),
+            Error::MovieDesync { frame } => write!(f, "Movie desynchronized at frame {frame}"),
+            Error::UnknownPort { port, direction, pc } => {
+                write!(f, "Unknown {direction} port {port} at ${pc:04X}")
+            }
+            Error::UnknownPortBits { port, bits, pc } => {
+                write!(f, "Unknown bits {bits:#010b} of output port {port} at ${pc:04X}")
+            }
+            Error::InvalidOpcode { opcode, pc, .. } => {
+                write!(f, "Invalid opcode ${opcode:02X} at ${pc:04X}")
+            }
+            Error::InvalidRoms { reason } => write!(f, "Invalid ROMs: {reason}"),
+            Error::InvalidHighScore { reason } => write!(f, "Invalid high score: {reason}"),
+            Error::GameNotStarted { frames } => {
+                write!(f, "The game has not started within {frames} frames")
+            }
+            Error::UnsupportedDipSwitch { key, setting } => {
+                write!(f, "Unsupported DIP switch setting: {key} = {setting}")
+            }
+            Error::InvalidSampleManifest { reason } => {
+                write!(f, "Invalid sample manifest: {reason}")
+            }
+            Error::Toml { source } => source.fmt(f),
+            Error::Zip { path, source } => write!(f, "{}: {source}", path.display()),
         }
     }
 }
@@ -39,6 +120,19 @@ impl std::error::Error for Error {
         match self {
             Error::I8080 { source } => Some(source),
             Error::Io { source } => Some(source),
+            Error::InvalidOpcode { source, .. } => Some(source),
+            Error::Toml { source } => Some(source),
+            Error::Zip { source, .. } => Some(source),
+            Error::InvalidSaveState { .. }
+            | Error::InvalidMovie { .. }
+            | Error::MovieDesync { .. }
+            | Error::UnknownPort { .. }
+            | Error::UnknownPortBits { .. }
+            | Error::InvalidRoms { .. }
+            | Error::InvalidHighScore { .. }
+            | Error::GameNotStarted { .. }
+            | Error::UnsupportedDipSwitch { .. }
+            | Error::InvalidSampleManifest { .. } => None,
         }
     }
 }
@@ -55,6 +149,56 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Toml { source: e }
+    }
+}
+
+/// The direction of an access to a port.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PortDirection {
+    /// An IN instruction.
+    In,
+    /// An OUT instruction.
+    Out,
+}
+
+impl Display for PortDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PortDirection::In => write!(f, "input"),
+            PortDirection::Out => write!(f, "output"),
+        }
+    }
+}
+
+/// What to do on an access to a port, or to bits of an output port, that are not mapped on the
+/// board.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnmappedIoPolicy {
+    /// Ignores the access. An IN instruction leaves the accumulator unchanged.
+    Ignore,
+    /// Ignores the access and logs it as a warning.
+    Log,
+    /// Fails with `Error::UnknownPort` or `Error::UnknownPortBits`.
+    #[default]
+    Fail,
+}
+
+impl FromStr for UnmappedIoPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(UnmappedIoPolicy::Ignore),
+            "log" => Ok(UnmappedIoPolicy::Log),
+            "fail" => Ok(UnmappedIoPolicy::Fail),
+            _ => Err(format!("unknown policy: {s} (expected ignore, log or fail)")),
+        }
+    }
+}
+
 /// A specialized `std::result::Result` type for this crate.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -63,65 +207,255 @@ pub const SCREEN_WIDTH: u32 = 224;
 /// The height of the screen of the Space Invaders arcade machine.
 pub const SCREEN_HEIGHT: u32 = 256;
 
-/// A Space Invaders arcade machine.
+/// The number of states that the Intel 8080 CPU executes per video frame (2 MHz / 60 Hz).
+pub const STATES_PER_FRAME: u32 = 33_333;
+/// The number of scanlines per video frame, including those in the vertical blanking interval.
+pub const SCANLINES_PER_FRAME: u32 = 262;
+
+/// A Space Invaders arcade machine, or another board of the family as given by a [`Machine`].
 pub struct SpaceInvaders {
     /// The Intel 8080 CPU.
     pub i8080: Intel8080,
-    interrupt_receiver: Receiver<[u8; 3]>,
+    machine: &'static Machine,
+    memory_map: MemoryMap,
+    interrupt_source: Box<dyn InterruptSource>,
+    interrupt_scheduler: InterruptScheduler,
     /// Port 1.
     pub port1: Port1,
     /// Port 2.
     pub port2: Port2,
-    port3: Port3,
-    port5: Port5,
+    // The bits written to the sound ports of the machine, ports 3 and 5 on all the boards.
+    sound_latches: [u8; 2],
     video_shifter: VideoShifter,
-    samples: Samples,
+    audio: Box<dyn AudioBackend>,
+    volume: Volume,
+    frame_info: FrameInfo,
+    rom_hashes: Vec<u32>,
+    unmapped_io_policy: UnmappedIoPolicy,
+    watchdog_frames: u8,
+    events: Vec<MachineEvent>,
+    // The high score to write to RAM once the game has initialized it at boot.
+    pending_high_score: Option<Vec<u8>>,
 }
 
 impl SpaceInvaders {
-    /// Constructs a new `SpaceInvaders`.
+    /// Constructs a new `SpaceInvaders` of the Space Invaders board, `machine::INVADERS`.
     ///
     /// # Arguments
     ///
-    /// * `roms` - a reference to a slice of paths to ROMs to be loaded starting at address 0.
-    /// * `samples` - an optional array of paths to 9 audio samples.
-    /// * `audio_stream_handle` - an optional reference to OutputStreamHandle,
-    /// * `interrupt_receiver` - a `std::sync::mpsc::Receiver` to receive interrupts from.
+    /// * `roms` - a reference to a slice of paths to invaders.h, invaders.g, invaders.f and
+    ///   invaders.e.
+    /// * `samples` - an optional array of paths to 10 audio samples. The sounds whose samples
+    ///   are missing are synthesized.
+    /// * `audio_stream_handle` - an optional reference to OutputStreamHandle.
     ///
     /// # Example
     ///
     /// ```no_run
-    /// use std::sync::mpsc;
     /// use rodio::OutputStream;
     /// use space_invaders::SpaceInvaders;
     ///
     /// let (_audio_stream, audio_stream_handle) = OutputStream::try_default()?;
-    /// let (interrupt_sender, interrupt_receiver) = mpsc::sync_channel(0);
     /// let space_invaders = SpaceInvaders::new(
     ///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
-    ///     Some(["1.wav", "2.wav", "3.wav", "4.wav", "5.wav", "6.wav", "7.wav", "8.wav", "9.wav"]),
+    ///     Some([
+    ///         "0.wav", "1.wav", "2.wav", "3.wav", "4.wav", "5.wav", "6.wav", "7.wav", "8.wav", "9.wav",
+    ///     ]),
     ///     Some(&audio_stream_handle),
-    ///     interrupt_receiver,
     /// )?;
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
         roms: &[P],
-        samples: Option<[Q; 9]>,
+        samples: Option<[Q; 10]>,
+        audio_stream_handle: Option<&OutputStreamHandle>,
+    ) -> Result<Self> {
+        Self::with_machine(&machine::INVADERS, roms, samples, audio_stream_handle)
+    }
+
+    /// Constructs a new `SpaceInvaders` of the board `machine`.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - the definition of the board.
+    /// * `roms` - a reference to a slice of paths to the ROMs of `machine`, in the order of
+    ///   `machine.roms`.
+    /// * `samples` - an optional array of paths to 10 audio samples, in the order of
+    ///   `SoundId::ALL`. The sounds whose samples are missing are synthesized.
+    /// * `audio_stream_handle` - an optional reference to OutputStreamHandle.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use space_invaders::{machine, SpaceInvaders};
+    ///
+    /// let space_invaders = SpaceInvaders::with_machine(
+    ///     &machine::LRESCUE,
+    ///     &["lrescue.1", "lrescue.2", "lrescue.3", "lrescue.4", "lrescue.5", "lrescue.6"],
+    ///     None::<[&str; 10]>,
+    ///     None,
+    /// )?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_machine<P: AsRef<Path>, Q: AsRef<Path>>(
+        machine: &'static Machine,
+        roms: &[P],
+        samples: Option<[Q; 10]>,
+        audio_stream_handle: Option<&OutputStreamHandle>,
+    ) -> Result<Self> {
+        let roms = roms.iter().map(fs::read).collect::<io::Result<Vec<_>>>()?;
+        let samples = match (samples, audio_stream_handle) {
+            (Some(samples), Some(_)) => samples.map(|path| {
+                let path = path.as_ref();
+                match fs::read(path) {
+                    Ok(wav) => Some(wav),
+                    Err(err) => {
+                        if let io::ErrorKind::NotFound = err.kind() {
+                            warn!("{:?}: '{}', synthesizing it", err, path.display());
+                        } else {
+                            warn!("{:?}", err);
+                        }
+                        None
+                    }
+                }
+            }),
+            _ => Default::default(),
+        };
+        Self::from_bytes(machine, &roms, samples, audio_stream_handle)
+    }
+
+    /// Constructs a new `SpaceInvaders` of the board `machine` from the contents of its ROMs and
+    /// samples, such as those read from a zip archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - the definition of the board.
+    /// * `roms` - a reference to a slice of the contents of the ROMs of `machine`, in the order
+    ///   of `machine.roms`.
+    /// * `samples` - an array of the contents of the 10 audio samples, in the order of
+    ///   `SoundId::ALL`. The sound of a sample that is `None` is synthesized.
+    /// * `audio_stream_handle` - an optional reference to OutputStreamHandle.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use space_invaders::{machine, SpaceInvaders};
+    ///
+    /// // JMP $0000
+    /// let mut invaders_h = vec![0; 0x800];
+    /// invaders_h[..3].copy_from_slice(&[0xC3, 0x00, 0x00]);
+    /// let roms = [invaders_h, vec![0; 0x800], vec![0; 0x800], vec![0; 0x800]];
+    /// let space_invaders =
+    ///     SpaceInvaders::from_bytes(&machine::INVADERS, &roms, Default::default(), None)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_bytes<R: AsRef<[u8]>>(
+        machine: &'static Machine,
+        roms: &[R],
+        samples: [Option<Vec<u8>>; 10],
         audio_stream_handle: Option<&OutputStreamHandle>,
-        interrupt_receiver: Receiver<[u8; 3]>,
     ) -> Result<Self> {
-        let samples = Samples::new(audio_stream_handle, samples);
-        Ok(Self {
-            i8080: Intel8080::new(roms, 0)?,
-            interrupt_receiver,
-            port1: Port1::default(),
-            port2: Port2::default(),
-            port3: Port3::default(),
-            port5: Port5::default(),
-            video_shifter: VideoShifter::default(),
-            samples,
-        })
+        let mut builder = Self::builder().machine(machine);
+        for rom in roms {
+            builder = builder.rom_bytes(rom.as_ref());
+        }
+        for (sound, sample) in SoundId::ALL.into_iter().zip(samples) {
+            if let Some(sample) = sample {
+                builder = builder.sample_bytes(sound, sample);
+            }
+        }
+        if let Some(audio_stream_handle) = audio_stream_handle {
+            builder = builder.audio(audio_stream_handle);
+        }
+        builder.build()
+    }
+
+    /// Returns a [`Builder`] of a `SpaceInvaders`, which takes the contents of the ROMs and the
+    /// samples, optional audio and an optional interrupt source.
+    pub fn builder<'a>() -> Builder<'a> {
+        Builder::new()
+    }
+
+    /// Returns the definition of the board.
+    pub fn machine(&self) -> &'static Machine {
+        self.machine
+    }
+
+    /// Returns the settings of the DIP switches.
+    pub fn dip_switches(&self) -> DipSwitches {
+        DipSwitches::from_port2(self.machine, self.port2)
+    }
+
+    /// Sets the DIP switches, leaving the other bits of `Port2` as they are. The DIP switches are
+    /// at their factory settings by default.
+    pub fn set_dip_switches(&mut self, dip_switches: DipSwitches) -> Result<()> {
+        let bits = dip_switches.to_port2(self.machine)?;
+        self.port2 =
+            (self.port2 - Port2::from_bits_truncate(self.machine.dip_switch_mask())) | bits;
+        Ok(())
+    }
+
+    /// Returns the volume controls.
+    pub fn volume(&self) -> &Volume {
+        &self.volume
+    }
+
+    /// Sets the volume controls, which apply at once to the sounds being played, such as the UFO
+    /// loop. The sounds are played at the volumes of their samples by default.
+    pub fn set_volume(&mut self, volume: Volume) {
+        self.audio.set_volume(&volume);
+        self.volume = volume;
+    }
+
+    /// Returns a view of the state of the game in RAM, as laid out by Space Invaders.
+    pub fn game_state(&self) -> GameState<'_> {
+        GameState::new(&self.i8080.memory)
+    }
+
+    /// Returns the high score as the game keeps it in RAM, or `None` if the machine does not
+    /// record where it is kept or if a high score set with [`SpaceInvaders::set_high_score`] is
+    /// yet to be restored.
+    pub fn high_score(&self) -> Option<&[u8]> {
+        let high_score = self.machine.high_score.as_ref()?;
+        match self.pending_high_score {
+            Some(_) => None,
+            None => {
+                let address = usize::from(high_score.address);
+                Some(&self.i8080.memory[address..address + usize::from(high_score.len)])
+            }
+        }
+    }
+
+    /// Restores a high score returned by [`SpaceInvaders::high_score`], as NVRAM would. It is
+    /// written to RAM once the game has initialized the high score at boot, so it has to be set
+    /// before the machine is run or reset.
+    ///
+    /// Fails if the machine does not record where the high score is kept, or if `high_score` is
+    /// not of its length.
+    pub fn set_high_score(&mut self, high_score: &[u8]) -> Result<()> {
+        let len = match &self.machine.high_score {
+            Some(high_score) => usize::from(high_score.len),
+            None => return Err(Error::InvalidHighScore { reason: "unknown location" }),
+        };
+        if high_score.len() != len {
+            return Err(Error::InvalidHighScore { reason: "wrong length" });
+        }
+        self.pending_high_score = Some(high_score.to_vec());
+        Ok(())
+    }
+
+    /// Writes the high score set with [`SpaceInvaders::set_high_score`] to RAM if the game has
+    /// just initialized it.
+    fn restore_high_score(&mut self) {
+        if let (Some(high_score), Some(bytes)) =
+            (&self.machine.high_score, &self.pending_high_score)
+        {
+            if self.i8080.cpu.pc == high_score.initialized_pc {
+                let address = usize::from(high_score.address);
+                self.i8080.memory[address..address + bytes.len()].copy_from_slice(bytes);
+                self.pending_high_score = None;
+            }
+        }
     }
 
     /// Returns a shared reference to the framebuffer.
@@ -129,90 +463,297 @@ impl SpaceInvaders {
         &self.i8080.memory[0x2400..0x4000]
     }
 
+    /// Returns `true` if the game has set the screen-flip line of the second sound port (port 5),
+    /// which a cocktail cabinet uses to turn the screen around during player 2's turn. An upright
+    /// cabinet ignores it.
+    pub fn flip_screen(&self) -> bool {
+        self.sound_latches[1] & FLIP_SCREEN != 0
+    }
+
+    /// Returns the CRC-32 checksums of the 2 KiB ROM banks, as loaded at power-on, in the order of
+    /// the ROMs of the machine.
+    pub fn rom_hashes(&self) -> &[u32] {
+        &self.rom_hashes
+    }
+
+    /// Returns a hash of the whole state of the machine, which can be used to check whether two
+    /// runs have diverged.
+    pub fn state_hash(&self) -> u32 {
+        let mut state = Vec::new();
+        self.save_state(&mut state).expect("writing to a Vec never fails");
+        // A save state ends with the CRC-32 checksum of the rest of it, and the checksum of a
+        // message followed by its own checksum is the same for all messages.
+        u32::from_le_bytes(state[state.len() - 4..].try_into().unwrap())
+    }
+
+    /// Sets whether writes to ROM, which are ignored, are logged as warnings. They are not logged
+    /// by default.
+    pub fn set_log_rom_writes(&mut self, log_rom_writes: bool) {
+        self.memory_map.set_log_rom_writes(log_rom_writes);
+    }
+
+    /// Sets what to do on an access to a port, or to bits of an output port, that are not mapped
+    /// on the board. The policy is `Machine::unmapped_io_policy` by default.
+    pub fn set_unmapped_io_policy(&mut self, unmapped_io_policy: UnmappedIoPolicy) {
+        self.unmapped_io_policy = unmapped_io_policy;
+    }
+
+    /// Removes and returns the events that have occurred since the last call.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = MachineEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Resets the CPU and silences the sound, as the reset line of the board does. Memory is left
+    /// as it is.
+    pub fn reset(&mut self) {
+        self.i8080.cpu.pc = 0;
+        self.i8080.cpu.interrupt_enabled = false;
+        self.jump_sound_latches([0; 2]);
+        self.watchdog_frames = 0;
+    }
+
+    /// Returns the number of states that the CPU has executed since power-on.
+    pub fn cycles(&self) -> u64 {
+        let scheduler = &self.interrupt_scheduler;
+        scheduler.frame_count * u64::from(STATES_PER_FRAME) + u64::from(scheduler.frame_states)
+    }
+
+    /// Returns the number of video frames that have been completed since power-on.
+    pub fn frame_count(&self) -> u64 {
+        self.interrupt_scheduler.frame_count
+    }
+
+    /// Runs the machine until the current video frame is completed.
+    ///
+    /// A frame started from the beginning consists of about `STATES_PER_FRAME` states, during
+    /// which both RST 1 and RST 2 are raised.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use space_invaders::SpaceInvaders;
+    ///
+    /// let mut space_invaders = SpaceInvaders::new(
+    ///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+    ///     None::<[&str; 10]>,
+    ///     None,
+    /// )?;
+    /// for _ in 0..60 {
+    ///     let frame_info = space_invaders.run_frame()?;
+    ///     println!("{} states", frame_info.states);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn run_frame(&mut self) -> Result<FrameInfo> {
+        let frame_count = self.frame_count();
+        self.frame_info = FrameInfo::default();
+        let mut states = 0;
+        while self.frame_count() == frame_count {
+            states += self.update()?;
+        }
+        Ok(FrameInfo { states, ..mem::take(&mut self.frame_info) })
+    }
+
     /// Handles a pending interrupt, if any; otherwise fetches and executes an instruction.
-    pub fn update(&mut self) -> u32 {
-        match self.interrupt_receiver.try_recv() {
-            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {
-                self.fetch_execute_instruction()
+    ///
+    /// Interrupts are raised as the interrupt source decides, by default when the number of states
+    /// executed in the current video frame reaches the position of the mid-screen interrupt (RST 1)
+    /// or the vertical blanking interrupt (RST 2). As on the board, whose interrupt request stays
+    /// asserted until the CPU acknowledges it, an interrupt raised while the CPU has disabled
+    /// interrupts is held until the CPU enables them, unless another one is raised meanwhile and
+    /// replaces it.
+    ///
+    /// Memory is accessed as on the board: writes to ROM are ignored, and addresses from 0x4000
+    /// up that are not ROM mirror RAM at 0x2000-0x3FFF. If the game has not written the watchdog
+    /// port for 255 frames, the watchdog resets the machine at the end of the frame and reports
+    /// `MachineEvent::WatchdogReset`.
+    ///
+    /// Returns the number of states taken. Fails if the instruction cannot be executed, or if it
+    /// accesses a port or bits of a port that are not mapped and the policy is
+    /// `UnmappedIoPolicy::Fail`, in which case the instruction has been executed nonetheless.
+    pub fn update(&mut self) -> Result<u32> {
+        self.restore_high_score();
+        let pc = self.i8080.cpu.pc;
+        let accesses = self.next_memory_accesses();
+        self.memory_map.before(&mut self.i8080.memory, accesses);
+        let interrupt = self.interrupt_scheduler.pending_interrupt(&*self.interrupt_source);
+        let (instruction, states) = match interrupt {
+            Some(instruction) if self.i8080.cpu.interrupt_enabled => {
+                self.interrupt_scheduler.acknowledge();
+                (None, self.i8080.interrupt(instruction)?)
+            }
+            _ => {
+                let (instruction, states) =
+                    self.i8080.fetch_execute_instruction().map_err(|source| {
+                        Error::InvalidOpcode {
+                            opcode: self.i8080.memory[usize::from(pc)],
+                            pc,
+                            source,
+                        }
+                    })?;
+                (Some(instruction), states)
             }
-            Ok(instruction) => self.i8080.interrupt(instruction).unwrap_or(0),
+        };
+        self.memory_map.after(&mut self.i8080.memory, accesses, pc);
+        let frame_count = self.frame_count();
+        self.interrupt_scheduler.advance(states, &*self.interrupt_source);
+        if self.frame_count() != frame_count {
+            self.audio.end_frame(self.cycles());
+            self.watchdog_frames = self.watchdog_frames.saturating_add(1);
+            if self.watchdog_frames == WATCHDOG_FRAMES {
+                self.reset();
+                self.events.push(MachineEvent::WatchdogReset { frame: self.frame_count() });
+            }
+        }
+        if let Some(instruction) = instruction {
+            self.handle_io(instruction, pc)?;
+        }
+        Ok(states)
+    }
+
+    /// Returns the memory that the next call to [`SpaceInvaders::update`] is going to access.
+    pub(crate) fn next_memory_accesses(&self) -> MemoryAccesses {
+        let cpu = &self.i8080.cpu;
+        // The scheduler is copied so that checking for a pending interrupt does not consume it.
+        let mut interrupt_scheduler = self.interrupt_scheduler;
+        match interrupt_scheduler.pending_interrupt(&*self.interrupt_source) {
+            Some(_) if cpu.interrupt_enabled => MemoryAccesses {
+                read: None,
+                write: Some((cpu.sp.wrapping_sub(2), 2)), // the return address
+            },
+            _ => disassembler::memory_accesses(cpu, &self.i8080.memory),
         }
     }
 
-    fn fetch_execute_instruction(&mut self) -> u32 {
-        let (instruction, states) = self.i8080.fetch_execute_instruction().unwrap();
+    /// Handles the instruction at `pc` if it is an IN or OUT instruction.
+    fn handle_io(&mut self, instruction: [u8; 3], pc: u16) -> Result<()> {
+        let machine = self.machine;
         match instruction {
             // OUT port
-            [0xD3, port, 0] => match port {
-                2 => self.video_shifter.offset = self.i8080.cpu.a,
-                3 => {
-                    // from_bits_unchecked() is used instead of from_bits() because the
-                    // functionalities of some bits of port 3 are not clear and they are ignored
-                    // for now.
-                    let port3 = unsafe { Port3::from_bits_unchecked(self.i8080.cpu.a) };
-                    if let Some((wav, sink)) = &self.samples.ufo_low_pitch {
-                        if port3.contains(Port3::UFO_LOW_PITCH) {
-                            if !self.port3.contains(Port3::UFO_LOW_PITCH) {
-                                match Decoder::new(Cursor::new(wav.clone())) {
-                                    Ok(source) => sink.append(source.repeat_infinite()),
-                                    Err(err) => warn!("{:?}", err),
-                                }
-                            }
-                        } else if self.port3.contains(Port3::UFO_LOW_PITCH) {
-                            sink.stop();
-                        }
-                    }
-                    for (audio, bit) in &mut [
-                        (&self.samples.shoot, Port3::SHOOT),
-                        (&self.samples.explosion, Port3::EXPLOSION),
-                        (&self.samples.invader_killed, Port3::INVADER_KILLED),
-                    ] {
-                        if let Some((wav, sink)) = audio {
-                            if port3.contains(*bit) && !self.port3.contains(*bit) {
-                                match Decoder::new(Cursor::new(wav.clone())) {
-                                    Ok(source) => sink.append(source),
-                                    Err(err) => warn!("{:?}", err),
-                                }
-                            }
-                        }
-                    }
-                    self.port3 = port3;
-                }
-                4 => self.video_shifter.shift_right(self.i8080.cpu.a),
-                5 => {
-                    let port5 = Port5::from_bits(self.i8080.cpu.a).unwrap();
-                    for (audio, bit) in &mut [
-                        (&self.samples.fast_invader_1, Port5::FAST_INVADER_1),
-                        (&self.samples.fast_invader_2, Port5::FAST_INVADER_2),
-                        (&self.samples.fast_invader_3, Port5::FAST_INVADER_3),
-                        (&self.samples.fast_invader_4, Port5::FAST_INVADER_4),
-                        (&self.samples.ufo_high_pitch, Port5::UFO_HIGH_PITCH),
-                    ] {
-                        if let Some((wav, sink)) = audio {
-                            if port5.contains(*bit) && !self.port5.contains(*bit) {
-                                match Decoder::new(Cursor::new(wav.clone())) {
-                                    Ok(source) => sink.append(source),
-                                    Err(err) => warn!("{:?}", err),
-                                }
-                            }
-                        }
-                    }
-                    self.port5 = port5;
+            [0xD3, port, 0] => {
+                let a = self.i8080.cpu.a;
+                if port == machine.shifter.amount {
+                    self.video_shifter.offset = a;
+                } else if port == machine.shifter.data {
+                    self.video_shifter.shift_right(a);
+                } else if let Some(index) = machine.sound_ports.iter().position(|&p| p == port) {
+                    self.write_sound_port(index, a, pc)?;
+                } else if port == machine.watchdog_port {
+                    self.watchdog_frames = 0;
+                } else {
+                    self.unmapped_io(Error::UnknownPort {
+                        port,
+                        direction: PortDirection::Out,
+                        pc,
+                    })?;
                 }
-                6 => (), // watchdog
-                _ => unreachable!(),
-            },
+            }
             // IN port
-            [0xDB, port, 0] => match port {
-                1 => self.i8080.cpu.a = self.port1.bits(),
-                2 => self.i8080.cpu.a = self.port2.bits(),
-                3 => self.i8080.cpu.a = self.video_shifter.into(),
-                _ => unreachable!(),
-            },
+            [0xDB, port, 0] => {
+                if port == machine.inputs.port1 {
+                    self.i8080.cpu.a = self.port1.bits();
+                } else if port == machine.inputs.port2 {
+                    self.i8080.cpu.a = self.port2.bits();
+                } else if port == machine.shifter.result {
+                    self.i8080.cpu.a = self.video_shifter.into();
+                } else {
+                    self.unmapped_io(Error::UnknownPort {
+                        port,
+                        direction: PortDirection::In,
+                        pc,
+                    })?;
+                }
+            }
             _ => (),
         }
-        states
+        Ok(())
+    }
+
+    /// Replaces the sound latches as the machine jumps to another point in time. The one-shot
+    /// sounds being played belong to the abandoned timeline, whereas a looping sound such as the
+    /// UFO keeps playing as long as its bit is set.
+    pub(crate) fn jump_sound_latches(&mut self, sound_latches: [u8; 2]) {
+        let cycle = self.cycles();
+        self.audio.stop_one_shot_sounds(cycle);
+        for output in self.machine.sounds.iter().filter(|output| output.looping) {
+            let bit = 1 << output.bit;
+            if let Some(index) = self.machine.sound_ports.iter().position(|&p| p == output.port) {
+                if (self.sound_latches[index] ^ sound_latches[index]) & bit != 0 {
+                    let on = sound_latches[index] & bit != 0;
+                    self.audio.sound_event(SoundEvent { id: output.sound, on, cycle });
+                }
+            }
+        }
+        self.audio.amplifier_event(sound_latches[0] & AMPLIFIER_ENABLED != 0, cycle);
+        self.sound_latches = sound_latches;
+    }
+
+    /// Handles `value` written to the `index`th sound port of the machine by the instruction at
+    /// `pc`, starting the sounds whose bits are set and stopping the loops whose bits are cleared.
+    fn write_sound_port(&mut self, index: usize, value: u8, pc: u16) -> Result<()> {
+        let port = self.machine.sound_ports[index];
+        let control = [AMPLIFIER_ENABLED, FLIP_SCREEN][index];
+        let known = self.machine.sound_bits(index) | control;
+        let latch = value & known;
+        let previous = mem::replace(&mut self.sound_latches[index], latch);
+        let cycle = self.cycles();
+        if index == 0 && (latch ^ previous) & AMPLIFIER_ENABLED != 0 {
+            self.audio.amplifier_event(latch & AMPLIFIER_ENABLED != 0, cycle);
+        }
+        for output in self.machine.sounds.iter().filter(|output| output.port == port) {
+            let bit = 1 << output.bit;
+            if (latch ^ previous) & bit != 0 {
+                let on = latch & bit != 0;
+                self.audio.sound_event(SoundEvent { id: output.sound, on, cycle });
+            }
+        }
+        match index {
+            0 => self.frame_info.port3_changed |= latch != previous,
+            // The screen-flip line is not a sound bit.
+            _ => self.frame_info.port5_changed |= (latch ^ previous) & !FLIP_SCREEN != 0,
+        }
+        self.check_unknown_bits(port, value, known, pc)
+    }
+
+    /// Handles `value` written to output port `port` by the instruction at `pc`, of which only
+    /// `known` bits are mapped on the board.
+    fn check_unknown_bits(&self, port: u8, value: u8, known: u8, pc: u16) -> Result<()> {
+        match value & !known {
+            0 => Ok(()),
+            bits => self.unmapped_io(Error::UnknownPortBits { port, bits, pc }),
+        }
     }
+
+    fn unmapped_io(&self, err: Error) -> Result<()> {
+        match self.unmapped_io_policy {
+            UnmappedIoPolicy::Ignore => Ok(()),
+            UnmappedIoPolicy::Log => {
+                warn!("{err}");
+                Ok(())
+            }
+            UnmappedIoPolicy::Fail => Err(err),
+        }
+    }
+}
+
+/// Information about a video frame run by [`SpaceInvaders::run_frame`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameInfo {
+    /// The number of states executed during the frame.
+    pub states: u32,
+    /// Whether the game changed the sound bits of the first sound port (port 3) during the frame.
+    pub port3_changed: bool,
+    /// Whether the game changed the sound bits of the second sound port (port 5) during the
+    /// frame.
+    pub port5_changed: bool,
+}
+
+/// An event that has occurred in the machine, as returned by [`SpaceInvaders::drain_events`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MachineEvent {
+    /// The watchdog has reset the machine at the end of frame `frame`, because the game had not
+    /// written the watchdog port for 255 frames.
+    WatchdogReset { frame: u64 },
 }
 
 bitflags! {
@@ -238,34 +779,113 @@ bitflags! {
     /// Port 2, which consists of bit flags.
     #[derive(Default)]
     pub struct Port2: u8 {
+        const NUM_OF_LIVES = 0b0000_0011;
         const TILT = 0b0000_0100;
         const EXTRA_LIFE_AT = 0b0000_1000;
         const PLAYER_2_FIRE = 0b0001_0000;
         const PLAYER_2_LEFT = 0b0010_0000;
         const PLAYER_2_RIGHT = 0b0100_0000;
         const PRICING_DISPLAY = 0b1000_0000;
+        const DIP_SWITCHES =
+            Self::NUM_OF_LIVES.bits | Self::EXTRA_LIFE_AT.bits | Self::PRICING_DISPLAY.bits;
     }
 }
 
-bitflags! {
-    // Some bits of port 3 are missing here because their functionalities are not clear.
-    #[derive(Default)]
-    struct Port3: u8 {
-        const UFO_LOW_PITCH = 0b0000_0001;
-        const SHOOT = 0b0000_0010;
-        const EXPLOSION = 0b0000_0100;
-        const INVADER_KILLED = 0b0000_1000;
+/// The bit of the first sound port that enables the amplifier. The sound is muted while it is
+/// cleared, as in the attract mode.
+const AMPLIFIER_ENABLED: u8 = 0b0010_0000;
+/// The bit of the second sound port that flips the screen of a cocktail cabinet.
+const FLIP_SCREEN: u8 = 0b0010_0000;
+
+/// The number of frames without a write to the watchdog port after which the watchdog resets the
+/// machine.
+const WATCHDOG_FRAMES: u8 = 255;
+
+/// The scanline at which the video hardware raises RST 1.
+const MID_SCREEN_SCANLINE: u32 = 96;
+/// The scanline at which the video hardware raises RST 2.
+const VBLANK_SCANLINE: u32 = 224;
+
+/// A source of the interrupts raised during each video frame.
+///
+/// The machine asks the source before every instruction whether to raise an interrupt instead,
+/// so the answer must depend only on the arguments. A frame ends once it has lasted
+/// `STATES_PER_FRAME` states and the source has no more interrupts to raise in it.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::InterruptSource;
+///
+/// /// Raises RST 2 once per frame, halfway through it.
+/// struct VBlankOnly;
+///
+/// impl InterruptSource for VBlankOnly {
+///     fn interrupt(&self, _frame: u64, frame_states: u32, raised: u8) -> Option<u8> {
+///         (raised == 0 && frame_states >= space_invaders::STATES_PER_FRAME / 2).then_some(2)
+///     }
+/// }
+/// ```
+pub trait InterruptSource: Send {
+    /// Returns the number of the RST instruction to raise once `frame_states` states of frame
+    /// `frame` have been executed and `raised` interrupts have been raised in it, if any.
+    fn interrupt(&self, frame: u64, frame_states: u32, raised: u8) -> Option<u8>;
+}
+
+/// The interrupts of the video hardware: RST 1 at the middle of the screen and RST 2 at the start
+/// of the vertical blanking interval, at their scanline positions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VideoInterrupts;
+
+impl InterruptSource for VideoInterrupts {
+    fn interrupt(&self, _frame: u64, frame_states: u32, raised: u8) -> Option<u8> {
+        match raised {
+            0 if frame_states >= MID_SCREEN_SCANLINE * STATES_PER_FRAME / SCANLINES_PER_FRAME => {
+                Some(1)
+            }
+            1 if frame_states >= VBLANK_SCANLINE * STATES_PER_FRAME / SCANLINES_PER_FRAME => {
+                Some(2)
+            }
+            _ => None,
+        }
     }
 }
 
-bitflags! {
-    #[derive(Default)]
-    struct Port5: u8 {
-        const FAST_INVADER_1 = 0b0000_0001;
-        const FAST_INVADER_2 = 0b0000_0010;
-        const FAST_INVADER_3 = 0b0000_0100;
-        const FAST_INVADER_4 = 0b0000_1000;
-        const UFO_HIGH_PITCH = 0b0001_0000;
+#[derive(Clone, Copy, Default)]
+struct InterruptScheduler {
+    frame_count: u64,
+    frame_states: u32,
+    // The number of interrupts raised in the current frame.
+    raised: u8,
+    // The RST number of the interrupt raised and not yet acknowledged by the CPU.
+    held: Option<u8>,
+}
+
+impl InterruptScheduler {
+    /// Returns the interrupt held for the CPU, as an RST instruction, if any. An interrupt that
+    /// `source` raises now replaces the one held.
+    fn pending_interrupt(&mut self, source: &dyn InterruptSource) -> Option<[u8; 3]> {
+        if let Some(rst) = source.interrupt(self.frame_count, self.frame_states, self.raised) {
+            self.raised += 1;
+            self.held = Some(rst & 0b111);
+        }
+        self.held.map(|rst| [0xC7 | (rst << 3), 0, 0])
+    }
+
+    /// Releases the interrupt held, which the CPU has taken.
+    fn acknowledge(&mut self) {
+        self.held = None;
+    }
+
+    fn advance(&mut self, states: u32, source: &dyn InterruptSource) {
+        self.frame_states += states;
+        if self.frame_states >= STATES_PER_FRAME
+            && source.interrupt(self.frame_count, self.frame_states, self.raised).is_none()
+        {
+            self.frame_states -= STATES_PER_FRAME;
+            self.frame_count += 1;
+            self.raised = 0;
+        }
     }
 }
 
@@ -287,72 +907,154 @@ impl From<VideoShifter> for u8 {
     }
 }
 
-struct Samples {
-    ufo_low_pitch: Option<(Vec<u8>, Sink)>,
-    shoot: Option<(Vec<u8>, Sink)>,
-    explosion: Option<(Vec<u8>, Sink)>,
-    invader_killed: Option<(Vec<u8>, Sink)>,
-    fast_invader_1: Option<(Vec<u8>, Sink)>,
-    fast_invader_2: Option<(Vec<u8>, Sink)>,
-    fast_invader_3: Option<(Vec<u8>, Sink)>,
-    fast_invader_4: Option<(Vec<u8>, Sink)>,
-    ufo_high_pitch: Option<(Vec<u8>, Sink)>,
-}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
 
-impl Samples {
-    fn new<P: AsRef<Path>>(
-        audio_stream_handle: Option<&OutputStreamHandle>,
-        samples: Option<[P; 9]>,
-    ) -> Self {
-        let mut ufo_low_pitch = None;
-        let mut shoot = None;
-        let mut explosion = None;
-        let mut invader_killed = None;
-        let mut fast_invader_1 = None;
-        let mut fast_invader_2 = None;
-        let mut fast_invader_3 = None;
-        let mut fast_invader_4 = None;
-        let mut ufo_high_pitch = None;
-        if let Some(samples) = samples {
-            if let Some(audio_stream_handle) = audio_stream_handle {
-                for (path, audio) in &mut [
-                    (&samples[0], &mut ufo_high_pitch),
-                    (&samples[1], &mut shoot),
-                    (&samples[2], &mut explosion),
-                    (&samples[3], &mut invader_killed),
-                    (&samples[4], &mut fast_invader_1),
-                    (&samples[5], &mut fast_invader_2),
-                    (&samples[6], &mut fast_invader_3),
-                    (&samples[7], &mut fast_invader_4),
-                    (&samples[8], &mut ufo_low_pitch),
-                ] {
-                    let path = path.as_ref();
-                    match fs::read(path) {
-                        Ok(wav) => match Sink::try_new(audio_stream_handle) {
-                            Ok(sink) => **audio = Some((wav, sink)),
-                            Err(err) => warn!("{:?}", err),
-                        },
-                        Err(err) => {
-                            if let io::ErrorKind::NotFound = err.kind() {
-                                warn!("{:?}: '{}'", err, path.display());
-                            } else {
-                                warn!("{:?}", err);
-                            }
-                        }
-                    }
+    /// Returns a builder of Space Invaders with `program` at the start of invaders.h and the rest
+    /// of the ROMs zeroed.
+    pub(crate) fn builder(program: &[u8]) -> Builder<'static> {
+        let mut invaders_h = vec![0; 0x800];
+        invaders_h[..program.len()].copy_from_slice(program);
+        SpaceInvaders::builder()
+            .rom_bytes(invaders_h)
+            .rom_bytes(vec![0; 0x800])
+            .rom_bytes(vec![0; 0x800])
+            .rom_bytes(vec![0; 0x800])
+    }
+
+    #[test]
+    fn video_interrupts() {
+        assert_eq!(MID_SCREEN_SCANLINE * STATES_PER_FRAME / SCANLINES_PER_FRAME, 12_213);
+        assert_eq!(VBLANK_SCANLINE * STATES_PER_FRAME / SCANLINES_PER_FRAME, 28_498);
+        assert_eq!(VideoInterrupts.interrupt(0, 12_212, 0), None);
+        assert_eq!(VideoInterrupts.interrupt(0, 12_213, 0), Some(1));
+        assert_eq!(VideoInterrupts.interrupt(0, 28_497, 1), None);
+        assert_eq!(VideoInterrupts.interrupt(0, 28_498, 1), Some(2));
+        assert_eq!(VideoInterrupts.interrupt(0, STATES_PER_FRAME, 2), None);
+    }
+
+    #[test]
+    fn interrupt_scheduling() {
+        // EI; JMP $0001, with EI; RET at RST 1 and RST 2.
+        let mut program = [0; 0x12];
+        program[..4].copy_from_slice(&[0xFB, 0xC3, 0x01, 0x00]);
+        program[0x08..0x0A].copy_from_slice(&[0xFB, 0xC9]);
+        program[0x10..0x12].copy_from_slice(&[0xFB, 0xC9]);
+        let mut space_invaders = builder(&program).build().unwrap();
+        space_invaders.i8080.cpu.sp = 0x2400;
+        let mut interrupts = Vec::new();
+        for _ in 0..2 {
+            let frame_count = space_invaders.frame_count();
+            let mut states = 0;
+            while space_invaders.frame_count() == frame_count {
+                let frame_states = space_invaders.interrupt_scheduler.frame_states;
+                states += space_invaders.update().unwrap();
+                if let pc @ (0x08 | 0x10) = space_invaders.i8080.cpu.pc {
+                    interrupts.push((pc / 8, frame_states));
                 }
             }
+            // A frame lasts until the instruction that crosses its end completes.
+            assert!((STATES_PER_FRAME..STATES_PER_FRAME + 10).contains(&states), "{states}");
+        }
+        // Each interrupt is taken at the end of the instruction that reaches its position, a JMP
+        // of 10 states.
+        let interrupt_states = interrupts.iter().map(|&(_, frame_states)| frame_states);
+        assert_eq!(interrupts.iter().map(|&(rst, _)| rst).collect::<Vec<_>>(), [1, 2, 1, 2]);
+        for (frame_states, position) in interrupt_states.zip([12_213, 28_498, 12_213, 28_498]) {
+            assert!((position..position + 10).contains(&frame_states), "{frame_states}");
+        }
+    }
+
+    #[test]
+    fn watchdog() {
+        // DI; JMP $0001
+        let mut space_invaders = builder(&[0xF3, 0xC3, 0x01, 0x00]).build().unwrap();
+        for _ in 0..254 {
+            space_invaders.run_frame().unwrap();
         }
-        Self {
-            ufo_low_pitch,
-            shoot,
-            explosion,
-            invader_killed,
-            fast_invader_1,
-            fast_invader_2,
-            fast_invader_3,
-            fast_invader_4,
-            ufo_high_pitch,
+        assert_eq!(space_invaders.drain_events().count(), 0);
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x0001);
+        space_invaders.run_frame().unwrap();
+        assert_eq!(
+            space_invaders.drain_events().collect::<Vec<_>>(),
+            [MachineEvent::WatchdogReset { frame: 255 }]
+        );
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x0000);
+
+        // OUT 6; JMP $0000
+        let mut space_invaders = builder(&[0xD3, 0x06, 0xC3, 0x00, 0x00]).build().unwrap();
+        for _ in 0..300 {
+            space_invaders.run_frame().unwrap();
         }
+        assert_eq!(space_invaders.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn high_score() {
+        // MVI A,$00; STA $20F4; STA $20F5; JMP $18DC, and JMP $18DC at 0x18DC, as the game
+        // initializes the high score at boot and then draws it from 0x18DC.
+        let mut invaders_h = vec![0; 0x800];
+        invaders_h[..11]
+            .copy_from_slice(&[0x3E, 0x00, 0x32, 0xF4, 0x20, 0x32, 0xF5, 0x20, 0xC3, 0xDC, 0x18]);
+        let mut invaders_e = vec![0; 0x800];
+        invaders_e[0xDC..0xDF].copy_from_slice(&[0xC3, 0xDC, 0x18]);
+        let mut space_invaders = SpaceInvaders::builder()
+            .rom_bytes(invaders_h)
+            .rom_bytes(vec![0; 0x800])
+            .rom_bytes(vec![0; 0x800])
+            .rom_bytes(invaders_e)
+            .build()
+            .unwrap();
+        assert_eq!(machine::INVADERS.high_score.as_ref().unwrap().initialized_pc, 0x18DC);
+        assert!(space_invaders.set_high_score(&[0x50]).is_err());
+        space_invaders.set_high_score(&[0x50, 0x12]).unwrap();
+        for _ in 0..4 {
+            space_invaders.update().unwrap();
+            assert_eq!(space_invaders.high_score(), None);
+        }
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x18DC);
+        space_invaders.update().unwrap();
+        assert_eq!(space_invaders.high_score(), Some(&[0x50, 0x12][..]));
+        assert_eq!(space_invaders.i8080.memory[0x20F4..0x20F6], [0x50, 0x12]);
+    }
+
+    #[test]
+    fn held_interrupts() {
+        // Runs DI; JMP $0001 until `frame_states` states into the first frame, and then EI at
+        // 0x0004 and the next update.
+        fn enable_interrupts_at(frame_states: u32) -> SpaceInvaders {
+            let mut space_invaders = builder(&[0xF3, 0xC3, 0x01, 0x00, 0xFB]).build().unwrap();
+            space_invaders.i8080.cpu.sp = 0x2400;
+            while space_invaders.interrupt_scheduler.frame_states < frame_states {
+                space_invaders.update().unwrap();
+            }
+            assert_eq!(space_invaders.i8080.cpu.pc, 0x0001);
+            space_invaders.i8080.cpu.pc = 0x0004;
+            space_invaders.update().unwrap();
+            space_invaders.update().unwrap();
+            space_invaders
+        }
+
+        // RST 1 is taken right after EI, although it has been raised long before.
+        let space_invaders = enable_interrupts_at(20_000);
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x0008);
+        assert_eq!(space_invaders.i8080.memory[0x23FE..0x2400], [0x05, 0x00]);
+        // RST 2 replaces RST 1 while interrupts are disabled.
+        let space_invaders = enable_interrupts_at(30_000);
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x0010);
+        assert_eq!(space_invaders.i8080.memory[0x23FE..0x2400], [0x05, 0x00]);
+    }
+
+    #[test]
+    fn refused_interrupts() {
+        // DI; JMP $0001
+        let mut space_invaders = builder(&[0xF3, 0xC3, 0x01, 0x00]).build().unwrap();
+        space_invaders.run_frame().unwrap();
+        space_invaders.run_frame().unwrap();
+        assert_eq!(space_invaders.frame_count(), 2);
+        assert_eq!(space_invaders.i8080.cpu.pc, 0x0001);
+        // No return address has been pushed.
+        assert!(space_invaders.i8080.memory[0x2000..].iter().all(|&byte| byte == 0));
     }
 }
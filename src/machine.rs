@@ -0,0 +1,414 @@
+//! Definitions of the boards of the Space Invaders family.
+//!
+//! The boards share the Intel 8080 CPU, the video shifter and the 1bpp framebuffer at
+//! 0x2400-0x3FFF, and differ in their ROMs, in the wiring of their ports and in their sounds.
+//!
+//! Only [`INVADERS`] has been checked against MAME. The definitions of the other boards record
+//! neither the hashes of their ROMs, of which only the sizes are verified, nor the locations of
+//! their high scores, which are therefore not kept. Their port maps are unchecked as well, so
+//! accesses to unmapped ports are logged rather than failing.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DipSwitchKey, UnmappedIoPolicy};
+
+/// A board of the Space Invaders family.
+#[derive(Debug)]
+pub struct Machine {
+    /// The short name of the board, as given to `--machine`.
+    pub name: &'static str,
+    /// The title of the game.
+    pub description: &'static str,
+    /// The ROMs, in the order in which their paths are given to
+    /// [`SpaceInvaders::with_machine`](crate::SpaceInvaders::with_machine).
+    pub roms: &'static [Rom],
+    /// The input ports from which `Port1` and `Port2` are read.
+    pub inputs: InputPorts,
+    /// The DIP switches, which are bits of `Port2`.
+    pub dip_switches: &'static [DipSwitch],
+    /// The output ports that latch the sound bits. Bit 5 of the first one enables the amplifier,
+    /// and bit 5 of the second one flips the screen.
+    pub sound_ports: [u8; 2],
+    /// The sounds triggered by bits of the sound ports.
+    pub sounds: &'static [SoundOutput],
+    /// The ports of the video shifter.
+    pub shifter: ShifterPorts,
+    /// The output port that resets the watchdog.
+    pub watchdog_port: u8,
+    /// What to do by default on an access to a port, or to bits of an output port, that are not
+    /// mapped on the board.
+    pub unmapped_io_policy: UnmappedIoPolicy,
+    /// Where the game keeps its high score, if known.
+    pub high_score: Option<HighScore>,
+}
+
+impl Machine {
+    /// Returns the machine whose short name is `name`, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use space_invaders::machine::Machine;
+    ///
+    /// let machine = Machine::by_name("lrescue").unwrap();
+    /// assert_eq!(machine.description, "Lunar Rescue");
+    /// ```
+    pub fn by_name(name: &str) -> Option<&'static Machine> {
+        MACHINES.iter().copied().find(|machine| machine.name == name)
+    }
+
+    /// Returns the bits of `Port2` that are DIP switches.
+    pub fn dip_switch_mask(&self) -> u8 {
+        self.dip_switches.iter().fold(0, |mask, dip_switch| mask | dip_switch.mask)
+    }
+
+    /// Returns the bits of the `index`th sound port that trigger sounds.
+    pub(crate) fn sound_bits(&self, index: usize) -> u8 {
+        self.sounds
+            .iter()
+            .filter(|sound| sound.port == self.sound_ports[index])
+            .fold(0, |bits, sound| bits | (1 << sound.bit))
+    }
+}
+
+/// A ROM of a [`Machine`].
+#[derive(Debug)]
+pub struct Rom {
+    /// The file name of the ROM.
+    pub name: &'static str,
+    /// Other file names under which the ROM is found, such as those of newer MAME sets.
+    pub aliases: &'static [&'static str],
+    /// The address at which the ROM is mapped.
+    pub address: u16,
+    /// The size of the ROM in bytes.
+    pub len: u16,
+    /// The hashes of a good dump, if known.
+    pub hashes: Option<RomHashes>,
+}
+
+impl Rom {
+    /// Returns `true` if `bytes` are a good dump of the ROM: they have the expected size, and the
+    /// expected hashes if those are known.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() == usize::from(self.len)
+            && self.hashes.as_ref().is_none_or(|hashes| {
+                crc32fast::hash(bytes) == hashes.crc32
+                    && sha1_smol::Sha1::from(bytes).digest().to_string() == hashes.sha1
+            })
+    }
+}
+
+/// The CRC-32 checksum and the SHA-1 digest of a good dump of a ROM.
+#[derive(Debug)]
+pub struct RomHashes {
+    /// The CRC-32 checksum.
+    pub crc32: u32,
+    /// The SHA-1 digest in lowercase hexadecimal.
+    pub sha1: &'static str,
+}
+
+/// The input ports of a [`Machine`].
+#[derive(Debug)]
+pub struct InputPorts {
+    /// The port from which `Port1` is read.
+    pub port1: u8,
+    /// The port from which `Port2` is read.
+    pub port2: u8,
+}
+
+/// A DIP switch of a [`Machine`], which occupies the bits `mask` of `Port2`.
+#[derive(Debug)]
+pub struct DipSwitch {
+    /// The setting of [`DipSwitches`](crate::DipSwitches) that the DIP switch sets.
+    pub key: DipSwitchKey,
+    /// The name of the DIP switch, for display.
+    pub name: &'static str,
+    /// The bits of `Port2` that the DIP switch occupies.
+    pub mask: u8,
+    /// The settings of the DIP switch, as the values of the bits `mask`, the values of the field
+    /// of `DipSwitches` with 1 and 0 for on and off, and their descriptions for display. The
+    /// first one is the factory setting.
+    pub settings: &'static [(u8, u16, &'static str)],
+}
+
+impl DipSwitch {
+    /// Returns the index in `settings` of the setting that `port2` selects, if any.
+    pub fn setting(&self, port2: u8) -> Option<usize> {
+        self.settings.iter().position(|&(bits, _, _)| bits == port2 & self.mask)
+    }
+}
+
+/// A sound of the Space Invaders family, which is played from the sample with the same number.
+///
+/// The other boards play their own sounds in place of those of Space Invaders.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundId {
+    UfoHighPitch = 0,
+    Shoot = 1,
+    Explosion = 2,
+    InvaderKilled = 3,
+    FastInvader1 = 4,
+    FastInvader2 = 5,
+    FastInvader3 = 6,
+    FastInvader4 = 7,
+    UfoLowPitch = 8,
+    ExtendedPlay = 9,
+}
+
+impl SoundId {
+    /// All the sounds, in the order of their numbers.
+    pub const ALL: [SoundId; 10] = [
+        SoundId::UfoHighPitch,
+        SoundId::Shoot,
+        SoundId::Explosion,
+        SoundId::InvaderKilled,
+        SoundId::FastInvader1,
+        SoundId::FastInvader2,
+        SoundId::FastInvader3,
+        SoundId::FastInvader4,
+        SoundId::UfoLowPitch,
+        SoundId::ExtendedPlay,
+    ];
+}
+
+/// A sound triggered by a bit of a sound port.
+#[derive(Debug)]
+pub struct SoundOutput {
+    /// The output port.
+    pub port: u8,
+    /// The bit number in the port.
+    pub bit: u8,
+    /// The sound, which starts when the bit is set.
+    pub sound: SoundId,
+    /// Whether the sound repeats until the bit is cleared, instead of playing once.
+    pub looping: bool,
+}
+
+/// The ports of the video shifter of a [`Machine`].
+#[derive(Debug)]
+pub struct ShifterPorts {
+    /// The output port that sets the shift amount.
+    pub amount: u8,
+    /// The output port that shifts a byte into the shift register.
+    pub data: u8,
+    /// The input port from which the shifted byte is read.
+    pub result: u8,
+}
+
+/// The RAM in which a [`Machine`] keeps its high score, which is lost at power-off.
+#[derive(Debug)]
+pub struct HighScore {
+    /// The address of the first byte of the high score.
+    pub address: u16,
+    /// The number of bytes of the high score.
+    pub len: u16,
+    /// The address of an instruction that the game reaches at boot once it has initialized the
+    /// high score, and before it displays it.
+    pub initialized_pc: u16,
+}
+
+const fn rom(name: &'static str, address: u16) -> Rom {
+    Rom { name, aliases: &[], address, len: 0x800, hashes: None }
+}
+
+const fn hashed_rom(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    address: u16,
+    crc32: u32,
+    sha1: &'static str,
+) -> Rom {
+    Rom { name, aliases, address, len: 0x800, hashes: Some(RomHashes { crc32, sha1 }) }
+}
+
+const fn sound(port: u8, bit: u8, sound: SoundId) -> SoundOutput {
+    SoundOutput { port, bit, sound, looping: false }
+}
+
+const INPUTS: InputPorts = InputPorts { port1: 1, port2: 2 };
+const SHIFTER: ShifterPorts = ShifterPorts { amount: 2, data: 4, result: 3 };
+
+const PRICING_DISPLAY: DipSwitch = DipSwitch {
+    key: DipSwitchKey::CoinInfo,
+    name: "pricing display",
+    mask: 0b1000_0000,
+    settings: &[(0, 1, "on"), (0x80, 0, "off")],
+};
+
+const INVADPT2_DIP_SWITCHES: &[DipSwitch] = &[
+    DipSwitch {
+        key: DipSwitchKey::Lives,
+        name: "number of lives",
+        mask: 0b0000_0001,
+        settings: &[(0, 3, "3"), (1, 4, "4")],
+    },
+    PRICING_DISPLAY,
+];
+
+// The sound board of Space Invaders, which Space Invaders Part II and Deluxe use as well.
+const INVADERS_SOUNDS: &[SoundOutput] = &[
+    SoundOutput { port: 3, bit: 0, sound: SoundId::UfoLowPitch, looping: true },
+    sound(3, 1, SoundId::Shoot),
+    sound(3, 2, SoundId::Explosion),
+    sound(3, 3, SoundId::InvaderKilled),
+    sound(3, 4, SoundId::ExtendedPlay),
+    sound(5, 0, SoundId::FastInvader1),
+    sound(5, 1, SoundId::FastInvader2),
+    sound(5, 2, SoundId::FastInvader3),
+    sound(5, 3, SoundId::FastInvader4),
+    sound(5, 4, SoundId::UfoHighPitch),
+];
+
+/// Space Invaders (Midway, 1978).
+pub static INVADERS: Machine = Machine {
+    name: "invaders",
+    description: "Space Invaders",
+    roms: &[
+        hashed_rom(
+            "invaders.h",
+            &["9316b-0869_m739h.h36"],
+            0x0000,
+            0x734f_5ad8,
+            "ff6200af4c9110d8181249cbcef1a8a40fa40b7f",
+        ),
+        hashed_rom(
+            "invaders.g",
+            &["9316b-0856_m739g.g36"],
+            0x0800,
+            0x6bfa_ca4a,
+            "16f48649b531bdef8c2d1446c429b5f414524350",
+        ),
+        hashed_rom(
+            "invaders.f",
+            &["9316b-0855_m739f.f36"],
+            0x1000,
+            0x0cce_ad96,
+            "537aef03468f63c5b9e11dd61e253f7ae17d9743",
+        ),
+        hashed_rom(
+            "invaders.e",
+            &["9316b-0854_m739e.e36"],
+            0x1800,
+            0x14e5_38b0,
+            "1d6ca0c99f9df71e2990b610deb9d7da0125e2d8",
+        ),
+    ],
+    inputs: INPUTS,
+    dip_switches: &[
+        DipSwitch {
+            key: DipSwitchKey::Lives,
+            name: "number of lives",
+            mask: 0b0000_0011,
+            settings: &[(0, 3, "3"), (1, 4, "4"), (2, 5, "5"), (3, 6, "6")],
+        },
+        DipSwitch {
+            key: DipSwitchKey::ExtraLifeAt,
+            name: "extra life at",
+            mask: 0b0000_1000,
+            settings: &[(0, 1500, "1500 points"), (0x08, 1000, "1000 points")],
+        },
+        PRICING_DISPLAY,
+    ],
+    sound_ports: [3, 5],
+    sounds: INVADERS_SOUNDS,
+    shifter: SHIFTER,
+    watchdog_port: 6,
+    unmapped_io_policy: UnmappedIoPolicy::Fail,
+    // At boot, 0x18D7 sets B to 0 and 0x18D9 calls the copy routine at 0x01E6, which then copies
+    // 256 bytes from 0x1B00 to 0x2000-0x20FF, the high score included. The call at 0x18DC, which
+    // follows it, draws the scores. A game start enters the routine at 0x01E4 instead, which sets
+    // B to 0xC0 and so leaves 0x20C0 up, the high score included, as it is.
+    high_score: Some(HighScore { address: 0x20F4, len: 2, initialized_pc: 0x18DC }),
+};
+
+/// Space Invaders Part II (Taito, 1979), which has a fifth ROM at 0x4000.
+pub static INVADPT2: Machine = Machine {
+    name: "invadpt2",
+    description: "Space Invaders Part II",
+    roms: &[
+        rom("pv01", 0x0000),
+        rom("pv02", 0x0800),
+        rom("pv03", 0x1000),
+        rom("pv04", 0x1800),
+        rom("pv05", 0x4000),
+    ],
+    inputs: INPUTS,
+    dip_switches: INVADPT2_DIP_SWITCHES,
+    sound_ports: [3, 5],
+    sounds: INVADERS_SOUNDS,
+    shifter: SHIFTER,
+    watchdog_port: 6,
+    unmapped_io_policy: UnmappedIoPolicy::Log,
+    high_score: None,
+};
+
+/// Space Invaders Deluxe (Midway, 1980), the Midway release of Space Invaders Part II.
+pub static INVADDLX: Machine = Machine {
+    name: "invaddlx",
+    description: "Space Invaders Deluxe",
+    roms: &[
+        rom("invdelux.h", 0x0000),
+        rom("invdelux.g", 0x0800),
+        rom("invdelux.f", 0x1000),
+        rom("invdelux.e", 0x1800),
+        rom("invdelux.d", 0x4000),
+    ],
+    inputs: INPUTS,
+    dip_switches: INVADPT2_DIP_SWITCHES,
+    sound_ports: [3, 5],
+    sounds: INVADERS_SOUNDS,
+    shifter: SHIFTER,
+    watchdog_port: 6,
+    unmapped_io_policy: UnmappedIoPolicy::Log,
+    high_score: None,
+};
+
+/// Lunar Rescue (Taito, 1979), which has two more ROMs at 0x4000.
+///
+/// Its sounds take the numbers of their closest Space Invaders counterparts: the thrust takes
+/// the low-pitched UFO sound, the footsteps and the bonus count take the first three fast
+/// invader sounds, the UFO thrust takes the fourth, and the shooting star takes the high-pitched
+/// UFO sound.
+pub static LRESCUE: Machine = Machine {
+    name: "lrescue",
+    description: "Lunar Rescue",
+    roms: &[
+        rom("lrescue.1", 0x0000),
+        rom("lrescue.2", 0x0800),
+        rom("lrescue.3", 0x1000),
+        rom("lrescue.4", 0x1800),
+        rom("lrescue.5", 0x4000),
+        rom("lrescue.6", 0x4800),
+    ],
+    inputs: INPUTS,
+    dip_switches: &[
+        DipSwitch {
+            key: DipSwitchKey::Lives,
+            name: "number of lives",
+            mask: 0b0000_0011,
+            settings: &[(0, 3, "3"), (1, 4, "4"), (2, 5, "5"), (3, 6, "6")],
+        },
+        PRICING_DISPLAY,
+    ],
+    sound_ports: [3, 5],
+    sounds: &[
+        sound(3, 0, SoundId::UfoLowPitch),
+        sound(3, 1, SoundId::Shoot),
+        sound(3, 2, SoundId::Explosion),
+        sound(3, 3, SoundId::InvaderKilled),
+        sound(3, 4, SoundId::ExtendedPlay),
+        sound(5, 0, SoundId::FastInvader1),
+        sound(5, 1, SoundId::FastInvader2),
+        sound(5, 2, SoundId::FastInvader3),
+        SoundOutput { port: 5, bit: 3, sound: SoundId::FastInvader4, looping: true },
+        SoundOutput { port: 5, bit: 4, sound: SoundId::UfoHighPitch, looping: true },
+    ],
+    shifter: SHIFTER,
+    watchdog_port: 6,
+    unmapped_io_policy: UnmappedIoPolicy::Log,
+    high_score: None,
+};
+
+/// All the machines, Space Invaders first.
+pub static MACHINES: [&Machine; 4] = [&INVADERS, &INVADPT2, &INVADDLX, &LRESCUE];
@@ -1,25 +1,30 @@
 #![warn(rust_2018_idioms)]
 
 use std::{
+    collections::BTreeMap,
+    env,
     fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
     mem::MaybeUninit,
-    path::PathBuf,
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
     process,
     sync::{
-        mpsc::{self, SyncSender},
+        atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use clap::Parser;
 
 use env_logger::Env;
 
-use log::info;
+use log::{info, warn};
 
-use glfw::{Action, Context, Key, SwapInterval, WindowEvent, WindowMode};
+use glfw::{Action, Context, Key, Modifiers, SwapInterval, WindowEvent, WindowMode};
 use luminance_derive::UniformInterface;
 use luminance_front::{
     context::GraphicsContext,
@@ -35,7 +40,17 @@ use luminance_glfw::{GL33Context, GlfwSurface, GlfwSurfaceError};
 
 use rodio::{OutputStream, StreamError};
 
-use space_invaders::{Port1, Port2, SpaceInvaders};
+use serde::{Deserialize, Serialize};
+
+use space_invaders::{
+    audio::{AudioBackend, NullBackend, RodioBackend, Volume, WavRecorder},
+    debugger::{Command, Debugger},
+    file_set::FileSet,
+    gdb::GdbStub,
+    machine::{Machine, SoundId, MACHINES},
+    rom_set, sample_set, DipSwitches, MachineEvent, MoviePlayer, MovieRecorder, Port1, Port2,
+    RewindBuffer, SpaceInvaders, UnmappedIoPolicy,
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -60,11 +75,178 @@ impl std::error::Error for Error {
 #[derive(Debug, Parser)]
 #[command(about)]
 struct Opt {
-    /// A directory that contains invaders.{e,f,g,h}
+    /// A directory or a zip archive that contains the ROMs of the machine, such as
+    /// invaders.{e,f,g,h}, or a directory that contains a zip archive named after the machine
     roms: PathBuf,
 
-    /// A directory that contains {0..8}.wav
+    /// A directory or a zip archive that contains {0..9}.wav, named samples such as shot.wav or a
+    /// samples.toml manifest, or a directory that contains a zip archive named after the machine
     samples: Option<PathBuf>,
+
+    /// A TOML manifest that maps the sounds to the files of the samples, instead of samples.toml
+    #[arg(long, value_name = "FILE", requires = "samples")]
+    sample_manifest: Option<PathBuf>,
+
+    /// The board to emulate: invaders, invadpt2, invaddlx or lrescue
+    #[arg(long, value_name = "NAME", default_value = "invaders", value_parser = parse_machine)]
+    machine: &'static Machine,
+
+    /// The number of lives at the start of a game, instead of that in the config file
+    #[arg(long, value_name = "N")]
+    lives: Option<u8>,
+
+    /// The score at which the extra life is awarded, instead of that in the config file
+    #[arg(long, value_name = "POINTS")]
+    extra_life: Option<u16>,
+
+    /// Hides the price of a game in the attract mode, instead of following the config file
+    #[arg(long, conflicts_with = "show_coin_info")]
+    hide_coin_info: bool,
+
+    /// Shows the price of a game in the attract mode, instead of following the config file
+    #[arg(long)]
+    show_coin_info: bool,
+
+    /// The config file, which stores the DIP switch settings of each machine [default:
+    /// space-invaders/config.toml in the user's config directory]
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Checks the ROMs of the machine against their known sizes and hashes, and exits
+    #[arg(long)]
+    verify_roms: bool,
+
+    /// Emulates a cocktail cabinet, which turns the screen around for player 2 and has separate
+    /// controls for each player
+    #[arg(long)]
+    cocktail: bool,
+
+    /// Logs the writes to ROM, which are ignored
+    #[arg(long)]
+    log_rom_writes: bool,
+
+    /// What to do on an access to an unmapped port: ignore, log or fail [default: fail for
+    /// invaders, log for the other machines]
+    #[arg(long, value_name = "POLICY")]
+    unmapped_io: Option<UnmappedIoPolicy>,
+
+    /// A directory to store save states in
+    #[arg(long, default_value = ".")]
+    state_dir: PathBuf,
+
+    /// Records the inputs from power-on to a movie file
+    #[arg(long, value_name = "FILE", conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+
+    /// Plays back a movie file from power-on
+    #[arg(long, value_name = "FILE")]
+    play_movie: Option<PathBuf>,
+
+    /// Plays back the movie as fast as possible without a window or audio output
+    #[arg(long, requires = "play_movie")]
+    headless: bool,
+
+    /// Records the sound, mixed in emulated time, to a WAV file
+    #[arg(long, value_name = "FILE")]
+    record_audio: Option<PathBuf>,
+
+    /// Starts paused with a debugger prompt on the terminal
+    #[arg(long, conflicts_with_all = ["record_movie", "play_movie"])]
+    debug: bool,
+
+    /// Listens for a GDB remote debugger on a local TCP port
+    #[arg(
+        long,
+        value_name = "PORT",
+        conflicts_with_all = ["record_movie", "play_movie", "debug"]
+    )]
+    gdb: Option<u16>,
+}
+
+/// The settings that hold across sessions, which are stored in a TOML file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Config {
+    /// The DIP switch settings of each machine, by the name of the machine.
+    #[serde(default)]
+    dip_switches: BTreeMap<String, DipSwitches>,
+
+    /// The volume controls, which are shared by the machines.
+    #[serde(default)]
+    volume: Volume,
+
+    /// The file the settings are stored in, if any.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Config {
+    /// Loads the settings from `path`, or returns the default settings if there is no such file.
+    fn load(path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(toml) => {
+                    toml::from_str(&toml).map_err(|err| format!("{}: {err}", path.display()))?
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+                Err(err) => return Err(err.into()),
+            },
+            None => Config::default(),
+        };
+        config.path = path;
+        Ok(config)
+    }
+
+    /// Stores the settings in the file they have been loaded from.
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, toml::to_string(self)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the user's config directory: `$XDG_CONFIG_HOME` or `~/.config` on Linux and other Unix
+/// systems, `~/Library/Application Support` on macOS, and `%APPDATA%` on Windows.
+fn config_dir() -> Option<PathBuf> {
+    user_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Returns the user's data directory: `$XDG_DATA_HOME` or `~/.local/share` on Linux and other Unix
+/// systems, and the same directory as `config_dir` on macOS and Windows.
+fn data_dir() -> Option<PathBuf> {
+    user_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn user_dir(xdg_var: &str, xdg_default: &str) -> Option<PathBuf> {
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        // The XDG Base Directory Specification has relative paths ignored.
+        var(xdg_var)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| var("HOME").map(|home| home.join(xdg_default)))
+    }
+}
+
+/// Requests from the window to the thread that runs the machine.
+#[derive(Default)]
+struct Requests {
+    rewind: AtomicBool,
+    debug_break: AtomicBool,
+    // Set when a state has been loaded, which resumes a machine halted by an error.
+    state_loaded: AtomicBool,
+}
+
+enum Movie {
+    None,
+    Recording(MovieRecorder<File>),
+    Playing(MoviePlayer<BufReader<File>>),
 }
 
 #[derive(UniformInterface)]
@@ -80,6 +262,15 @@ const FRAMEBUFFER_LEN: usize =
 const TEXELS_LEN: usize =
     space_invaders::SCREEN_HEIGHT as usize * space_invaders::SCREEN_WIDTH as usize;
 
+const REWIND_FRAMES: usize = 60 * 60;
+
+fn parse_machine(name: &str) -> Result<&'static Machine, String> {
+    Machine::by_name(name).ok_or_else(|| {
+        let names = MACHINES.iter().map(|machine| machine.name).collect::<Vec<_>>();
+        format!("unknown machine: {name} (expected one of {})", names.join(", "))
+    })
+}
+
 fn main() {
     if let Err(err) = run(Opt::parse()) {
         eprintln!("Error: {err}");
@@ -90,44 +281,150 @@ fn main() {
 fn run(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let (_audio_stream, audio_stream_handle) = match OutputStream::try_default() {
-        Ok((stream, stream_handle)) => (Some(stream), Some(stream_handle)),
-        Err(StreamError::NoDevice) => (None, None),
-        Err(err) => return Err(Box::new(err)),
+    let rom_files = FileSet::open(&opt.roms, opt.machine.name)?;
+    let rom_checks = rom_set::verify(opt.machine, &rom_files)?;
+    if opt.verify_roms {
+        for rom_check in &rom_checks {
+            println!("{rom_check}");
+        }
+        return match rom_checks.iter().all(|rom_check| rom_check.is_good()) {
+            true => Ok(()),
+            false => Err(format!("bad ROM set for {}", opt.machine.name).into()),
+        };
+    }
+    let mut roms = Vec::with_capacity(rom_checks.len());
+    for rom_check in &rom_checks {
+        match rom_check.bytes() {
+            Some(bytes) => {
+                if !rom_check.is_good() {
+                    warn!("{rom_check}");
+                }
+                roms.push(bytes);
+            }
+            None => return Err(format!("{rom_check} (see --verify-roms)").into()),
+        }
+    }
+
+    let (_audio_stream, audio_stream_handle) = if opt.headless {
+        (None, None)
+    } else {
+        match OutputStream::try_default() {
+            Ok((stream, stream_handle)) => (Some(stream), Some(stream_handle)),
+            Err(StreamError::NoDevice) => (None, None),
+            Err(err) => return Err(Box::new(err)),
+        }
+    };
+    let samples = match &opt.samples {
+        Some(samples) if audio_stream_handle.is_some() || opt.record_audio.is_some() => {
+            let manifest = match &opt.sample_manifest {
+                Some(path) => Some(fs::read_to_string(path)?.parse::<sample_set::Manifest>()?),
+                None => None,
+            };
+            sample_set::read(&FileSet::open(samples, opt.machine.name)?, manifest.as_ref())?
+        }
+        _ => Default::default(),
     };
-    let (interrupt_sender, interrupt_receiver) = mpsc::sync_channel(0);
-    let space_invaders = Arc::new(Mutex::new(SpaceInvaders::new(
-        &[
-            opt.roms.join("invaders.h"),
-            opt.roms.join("invaders.g"),
-            opt.roms.join("invaders.f"),
-            opt.roms.join("invaders.e"),
-        ],
-        opt.samples.map(|samples| {
-            [
-                samples.join("0.wav"),
-                samples.join("1.wav"),
-                samples.join("2.wav"),
-                samples.join("3.wav"),
-                samples.join("4.wav"),
-                samples.join("5.wav"),
-                samples.join("6.wav"),
-                samples.join("7.wav"),
-                samples.join("8.wav"),
-            ]
+    let audio_recorder = match &opt.record_audio {
+        Some(path) => {
+            let file = BufWriter::new(File::create(path)?);
+            Some(WavRecorder::new(file, samples.clone(), opt.machine)?)
+        }
+        None => None,
+    };
+    let audio_backend: Box<dyn AudioBackend> = match (&audio_stream_handle, &audio_recorder) {
+        (Some(handle), Some(recorder)) => {
+            Box::new((RodioBackend::new(handle, samples, opt.machine), recorder.clone()))
+        }
+        (Some(handle), None) => Box::new(RodioBackend::new(handle, samples, opt.machine)),
+        (None, Some(recorder)) => Box::new(recorder.clone()),
+        (None, None) => Box::new(NullBackend),
+    };
+    let mut builder = SpaceInvaders::builder().machine(opt.machine).audio_backend(audio_backend);
+    for rom in roms {
+        builder = builder.rom_bytes(rom);
+    }
+    let mut space_invaders = builder.build()?;
+    let config_path = opt
+        .config
+        .clone()
+        .or_else(|| config_dir().map(|dir| dir.join("space-invaders").join("config.toml")));
+    let mut config = Config::load(config_path)?;
+    let mut dip_switches = config.dip_switches.get(opt.machine.name).copied().unwrap_or_default();
+    dip_switches.lives = opt.lives.unwrap_or(dip_switches.lives);
+    dip_switches.extra_life_at = opt.extra_life.unwrap_or(dip_switches.extra_life_at);
+    dip_switches.coin_info = (dip_switches.coin_info || opt.show_coin_info) && !opt.hide_coin_info;
+    space_invaders.set_dip_switches(dip_switches)?;
+    space_invaders.set_volume(config.volume.clone());
+    // A movie has to be played back from the same power-on state as it was recorded from.
+    let high_score_path = match (&opt.machine.high_score, &opt.record_movie, &opt.play_movie) {
+        (Some(_), None, None) => data_dir().map(|dir| {
+            dir.join("space-invaders").join("hiscore").join(format!("{}.hi", opt.machine.name))
         }),
-        audio_stream_handle.as_ref(),
-        interrupt_receiver,
-    )?));
-    thread::spawn(update_space_invaders(Arc::clone(&space_invaders)));
-    thread::spawn(generate_interrupts(interrupt_sender));
+        _ => None,
+    };
+    if let Some(path) = &high_score_path {
+        match fs::read(path) {
+            Ok(high_score) => {
+                if let Err(err) = space_invaders.set_high_score(&high_score) {
+                    warn!("cannot restore the high score from '{}': {err}", path.display());
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => warn!("cannot restore the high score from '{}': {err}", path.display()),
+        }
+    }
+    space_invaders.set_log_rom_writes(opt.log_rom_writes);
+    if let Some(unmapped_io) = opt.unmapped_io {
+        space_invaders.set_unmapped_io_policy(unmapped_io);
+    }
+    let movie = if let Some(path) = &opt.record_movie {
+        Movie::Recording(MovieRecorder::new(File::create(path)?, &space_invaders)?)
+    } else if let Some(path) = &opt.play_movie {
+        let file = BufReader::new(File::open(path)?);
+        Movie::Playing(MoviePlayer::new(file, &mut space_invaders)?)
+    } else {
+        Movie::None
+    };
+    if opt.headless {
+        if let Movie::Playing(mut player) = movie {
+            while player.run_frame(&mut space_invaders)?.is_some() {
+                log_events(&mut space_invaders);
+            }
+            info!("played back {} frames", player.frame());
+        }
+        if let Some(recorder) = &audio_recorder {
+            recorder.finish()?;
+        }
+        return Ok(());
+    }
+    let gdb_listener = match opt.gdb {
+        Some(port) => {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+            listener.set_nonblocking(true)?;
+            info!("listening for a debugger on {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+    let space_invaders = Arc::new(Mutex::new(space_invaders));
+    let requests = Arc::new(Requests::default());
+    if opt.debug {
+        thread::spawn(debug_space_invaders(Arc::clone(&space_invaders), Arc::clone(&requests)));
+    } else {
+        thread::spawn(update_space_invaders(
+            Arc::clone(&space_invaders),
+            Arc::clone(&requests),
+            movie,
+            gdb_listener,
+        ));
+    }
 
     let mut surface = GlfwSurface::new(|glfw| {
         let (mut window, events) = glfw
             .create_window(
                 space_invaders::SCREEN_WIDTH * 2,
                 space_invaders::SCREEN_HEIGHT * 2,
-                "Space Invaders",
+                opt.machine.description,
                 WindowMode::Windowed,
             )
             .ok_or(GlfwSurfaceError::UserError(Error::CannotCreateError))?;
@@ -136,47 +433,210 @@ fn run(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
         glfw.set_swap_interval(SwapInterval::Sync(1));
         Ok((window, events))
     })?;
-    let mut graphics = Graphics::new(&mut surface.context)?;
+    let mut graphics = Graphics::new(&mut surface.context, opt.cocktail)?;
 
+    // The sound whose gain Shift+Minus and Shift+Equal change.
+    let mut selected_sound = SoundId::UfoLowPitch;
     let mut interval = spin_sleep_util::interval(Duration::from_secs(1) / 60);
     loop {
         interval.tick();
-        if !(process_input(&mut surface, &mut graphics, &space_invaders)?) {
+        if !(process_input(
+            &mut surface,
+            &mut graphics,
+            &space_invaders,
+            &requests,
+            &opt,
+            &mut config,
+            &mut selected_sound,
+        )?) {
             break;
         }
         graphics.render(&space_invaders, &mut surface.context)?;
     }
+    if let Some(path) = &high_score_path {
+        if let Err(err) = save_high_score(&space_invaders.lock().unwrap(), path) {
+            warn!("cannot save the high score to '{}': {err}", path.display());
+        }
+    }
+    if let Some(recorder) = &audio_recorder {
+        recorder.finish()?;
+    }
     Ok(())
 }
 
-fn update_space_invaders(space_invaders: Arc<Mutex<SpaceInvaders>>) -> impl FnOnce() {
+/// Writes the high score that the game keeps in RAM to `path`, unless the one restored at startup
+/// is yet to be written back to RAM.
+fn save_high_score(space_invaders: &SpaceInvaders, path: &Path) -> io::Result<()> {
+    if let Some(high_score) = space_invaders.high_score() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, high_score)?;
+    }
+    Ok(())
+}
+
+fn update_space_invaders(
+    space_invaders: Arc<Mutex<SpaceInvaders>>,
+    requests: Arc<Requests>,
+    mut movie: Movie,
+    gdb_listener: Option<TcpListener>,
+) -> impl FnOnce() {
     move || {
-        let mut interval = spin_sleep_util::interval(Duration::from_secs(1) / 120);
-        let mut timer = Instant::now();
+        let mut interval = spin_sleep_util::interval(Duration::from_secs(1) / 60);
+        let mut rewind_buffer = RewindBuffer::new(REWIND_FRAMES);
+        let mut halted = false;
         loop {
             interval.tick();
-            // 2 MHz = 2,000,000 states per second = 2 states per microsecond
-            let elapsed_states = timer.elapsed().as_micros() * 2;
-            timer = Instant::now();
-            let mut states = 0;
-            while elapsed_states > states {
-                states += u128::from(space_invaders.lock().unwrap().update());
+            if requests.state_loaded.swap(false, Ordering::Relaxed) {
+                halted = false;
+            }
+            if let Some(listener) = &gdb_listener {
+                match listener.accept() {
+                    Ok((stream, address)) => {
+                        info!("debugger connected from {address}");
+                        match GdbStub::new(stream).and_then(|mut stub| stub.serve(&space_invaders))
+                        {
+                            Ok(()) => info!("debugger disconnected"),
+                            Err(err) => warn!("debugger disconnected: {err}"),
+                        }
+                        // The debugger may have changed the machine behind the rewind buffer.
+                        rewind_buffer.clear();
+                        interval = spin_sleep_util::interval(Duration::from_secs(1) / 60);
+                        continue;
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => (),
+                    Err(err) => warn!("cannot accept a debugger: {err}"),
+                }
+            }
+            let mut space_invaders = space_invaders.lock().unwrap();
+            let result = match &mut movie {
+                Movie::None if requests.rewind.load(Ordering::Relaxed) => {
+                    // The keys being held now, rather than those held back then, have to drive
+                    // the game once rewinding stops.
+                    let (port1, port2) = (space_invaders.port1, space_invaders.port2);
+                    let result = rewind_buffer.rewind(&mut space_invaders).map(|rewound| {
+                        halted &= !rewound;
+                    });
+                    (space_invaders.port1, space_invaders.port2) = (port1, port2);
+                    result
+                }
+                Movie::None if halted => Ok(()),
+                Movie::None => {
+                    space_invaders.run_frame().and_then(|_| rewind_buffer.push(&space_invaders))
+                }
+                Movie::Recording(recorder) => recorder.run_frame(&mut space_invaders).map(|_| ()),
+                Movie::Playing(player) => match player.run_frame(&mut space_invaders) {
+                    Ok(Some(_)) => Ok(()),
+                    Ok(None) => {
+                        info!("played back {} frames", player.frame());
+                        movie = Movie::None;
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                },
+            };
+            log_events(&mut space_invaders);
+            match result {
+                Ok(()) => (),
+                Err(
+                    err @ (space_invaders::Error::I8080 { .. }
+                    | space_invaders::Error::UnknownPort { .. }
+                    | space_invaders::Error::UnknownPortBits { .. }
+                    | space_invaders::Error::InvalidOpcode { .. }),
+                ) => {
+                    warn!("{err}; rewind or load a state to resume");
+                    halted = true;
+                    movie = Movie::None;
+                }
+                Err(err) => {
+                    warn!("{err}");
+                    rewind_buffer.clear();
+                    movie = Movie::None;
+                }
             }
         }
     }
 }
 
-fn generate_interrupts(interrupt_sender: SyncSender<[u8; 3]>) -> impl FnOnce() {
+fn debug_space_invaders(
+    space_invaders: Arc<Mutex<SpaceInvaders>>,
+    requests: Arc<Requests>,
+) -> impl FnOnce() {
     move || {
-        let mut interval = spin_sleep_util::interval(Duration::from_secs(1) / 120);
+        let mut debugger = Debugger::new();
+        let mut lines = io::stdin().lock().lines();
+        let mut last_command = None;
+        let mut interval = spin_sleep_util::interval(Duration::from_secs(1) / 60);
+        let mut running = false;
+        println!("type `help` for the list of commands");
+        let _ = debugger.write_location(&space_invaders.lock().unwrap(), io::stdout());
         loop {
-            interval.tick();
-            if interrupt_sender.send([0xCF, 0, 0] /* RST 1 */).is_err() {
-                break;
+            if running {
+                interval.tick();
+                let mut space_invaders = space_invaders.lock().unwrap();
+                let result = debugger.run_frame(&mut space_invaders);
+                log_events(&mut space_invaders);
+                let stopped = match result {
+                    Ok(Some(address)) => {
+                        println!("breakpoint at ${address:04X}");
+                        true
+                    }
+                    Ok(None) => requests.debug_break.swap(false, Ordering::Relaxed),
+                    Err(err) => {
+                        println!("{err}");
+                        true
+                    }
+                };
+                if stopped {
+                    let _ = debugger.write_location(&space_invaders, io::stdout());
+                } else {
+                    continue;
+                }
             }
-            interval.tick();
-            if interrupt_sender.send([0xD7, 0, 0] /* RST 2 */).is_err() {
-                break;
+            running = false;
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(_)) | None => process::exit(0),
+            };
+            // An empty line repeats the last command, as in GDB.
+            let command = match (line.parse(), last_command) {
+                (Ok(command), _) => command,
+                (Err(_), Some(command)) if line.trim().is_empty() => command,
+                (Err(err), _) => {
+                    println!("{err}");
+                    continue;
+                }
+            };
+            last_command = Some(command);
+            if command == Command::Quit {
+                process::exit(0);
+            }
+            let mut space_invaders = space_invaders.lock().unwrap();
+            match debugger.execute(command, &mut space_invaders, io::stdout()) {
+                Ok(true) => {
+                    requests.debug_break.store(false, Ordering::Relaxed);
+                    // Start afresh rather than catching up with the ticks missed while paused.
+                    interval = spin_sleep_util::interval(Duration::from_secs(1) / 60);
+                    running = true;
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    println!("{err}");
+                    let _ = debugger.write_location(&space_invaders, io::stdout());
+                }
+            }
+        }
+    }
+}
+
+fn log_events(space_invaders: &mut SpaceInvaders) {
+    for event in space_invaders.drain_events() {
+        match event {
+            MachineEvent::WatchdogReset { frame } => {
+                warn!("the watchdog reset the machine at frame {frame}")
             }
         }
     }
@@ -190,10 +650,11 @@ struct Graphics {
     vertices: Tess<()>,
     texture: Texture<Dim2, NormR8UI>,
     texels: [<NormR8UI as Pixel>::Encoding; TEXELS_LEN],
+    cocktail: bool,
 }
 
 impl Graphics {
-    fn new(context: &mut GL33Context) -> Result<Self, Box<dyn std::error::Error>> {
+    fn new(context: &mut GL33Context, cocktail: bool) -> Result<Self, Box<dyn std::error::Error>> {
         let back_buffer = context.back_buffer()?;
         let pipeline_state = PipelineState::default().set_clear_depth(None);
         let BuiltProgram { program, warnings } =
@@ -213,7 +674,16 @@ impl Graphics {
             TexelUpload::reserve(0),
         )?;
         let texels = [0; TEXELS_LEN];
-        Ok(Self { back_buffer, pipeline_state, program, render_state, vertices, texture, texels })
+        Ok(Self {
+            back_buffer,
+            pipeline_state,
+            program,
+            render_state,
+            vertices,
+            texture,
+            texels,
+            cocktail,
+        })
     }
 
     fn render(
@@ -229,17 +699,22 @@ impl Graphics {
             vertices,
             texture,
             texels,
+            cocktail,
         } = self;
 
-        let framebuffer = unsafe {
-            let mut framebuffer = MaybeUninit::<[u8; FRAMEBUFFER_LEN]>::uninit();
-            (framebuffer.as_mut_ptr() as *mut u8).copy_from_nonoverlapping(
-                space_invaders.lock().unwrap().framebuffer() as *const [u8] as *const u8,
-                FRAMEBUFFER_LEN,
-            );
-            framebuffer.assume_init()
+        let (framebuffer, flipped) = {
+            let space_invaders = space_invaders.lock().unwrap();
+            let framebuffer = unsafe {
+                let mut framebuffer = MaybeUninit::<[u8; FRAMEBUFFER_LEN]>::uninit();
+                (framebuffer.as_mut_ptr() as *mut u8).copy_from_nonoverlapping(
+                    space_invaders.framebuffer() as *const [u8] as *const u8,
+                    FRAMEBUFFER_LEN,
+                );
+                framebuffer.assume_init()
+            };
+            (framebuffer, *cocktail && space_invaders.flip_screen())
         };
-        framebuffer_to_texels(&framebuffer, texels);
+        framebuffer_to_texels(&framebuffer, flipped, texels);
         texture.upload(TexelUpload::base_level(texels, 0))?;
         context
             .new_pipeline_gate()
@@ -281,32 +756,59 @@ const fn byte_to_texels() -> [[u8; 8]; 256] {
 
 fn framebuffer_to_texels(
     framebuffer: &[u8],
+    flipped: bool,
     texels: &mut [<NormR8UI as Pixel>::Encoding; TEXELS_LEN],
 ) {
     framebuffer.iter().enumerate().for_each(|(i, &byte)| {
         texels[i * 8..(i + 1) * 8].copy_from_slice(&BYTE_TO_TEXELS[usize::from(byte)]);
     });
+    if flipped {
+        // Reversing the texels in row-major order turns the image around by 180 degrees.
+        texels.reverse();
+    }
 }
 
 fn process_input(
     surface: &mut GlfwSurface,
     graphics: &mut Graphics,
     space_invaders: &Mutex<SpaceInvaders>,
+    requests: &Requests,
+    opt: &Opt,
+    config: &mut Config,
+    selected_sound: &mut SoundId,
 ) -> Result<bool, FramebufferError> {
     let mut resized = false;
     surface.context.window.glfw.poll_events();
     for (_, event) in surface.events_rx.try_iter() {
         match event {
+            WindowEvent::Key(key, _, Action::Press, modifiers)
+                if modifiers.intersects(Modifiers::Control | Modifiers::Alt)
+                    && save_state_slot(key).is_some() =>
+            {
+                let slot = save_state_slot(key).unwrap();
+                let path = opt.state_dir.join(format!("{}.{slot}.state", opt.machine.name));
+                if modifiers.contains(Modifiers::Control) {
+                    save_state(space_invaders, &path);
+                } else if opt.record_movie.is_some() || opt.play_movie.is_some() {
+                    warn!("cannot load a state while a movie is being recorded or played back");
+                } else if load_state(space_invaders, &path) {
+                    requests.state_loaded.store(true, Ordering::Relaxed);
+                }
+            }
             WindowEvent::Key(Key::Left, _, action, _) => match action {
                 Action::Press => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.insert(Port1::PLAYER_1_LEFT);
-                    space_invaders.port2.insert(Port2::PLAYER_2_LEFT);
+                    if !opt.cocktail {
+                        space_invaders.port2.insert(Port2::PLAYER_2_LEFT);
+                    }
                 }
                 Action::Release => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.remove(Port1::PLAYER_1_LEFT);
-                    space_invaders.port2.remove(Port2::PLAYER_2_LEFT);
+                    if !opt.cocktail {
+                        space_invaders.port2.remove(Port2::PLAYER_2_LEFT);
+                    }
                 }
                 Action::Repeat => (),
             },
@@ -314,12 +816,16 @@ fn process_input(
                 Action::Press => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.insert(Port1::PLAYER_1_RIGHT);
-                    space_invaders.port2.insert(Port2::PLAYER_2_RIGHT);
+                    if !opt.cocktail {
+                        space_invaders.port2.insert(Port2::PLAYER_2_RIGHT);
+                    }
                 }
                 Action::Release => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.remove(Port1::PLAYER_1_RIGHT);
-                    space_invaders.port2.remove(Port2::PLAYER_2_RIGHT);
+                    if !opt.cocktail {
+                        space_invaders.port2.remove(Port2::PLAYER_2_RIGHT);
+                    }
                 }
                 Action::Repeat => (),
             },
@@ -327,12 +833,43 @@ fn process_input(
                 Action::Press => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.insert(Port1::PLAYER_1_FIRE);
-                    space_invaders.port2.insert(Port2::PLAYER_2_FIRE);
+                    if !opt.cocktail {
+                        space_invaders.port2.insert(Port2::PLAYER_2_FIRE);
+                    }
                 }
                 Action::Release => {
                     let mut space_invaders = space_invaders.lock().unwrap();
                     space_invaders.port1.remove(Port1::PLAYER_1_FIRE);
-                    space_invaders.port2.remove(Port2::PLAYER_2_FIRE);
+                    if !opt.cocktail {
+                        space_invaders.port2.remove(Port2::PLAYER_2_FIRE);
+                    }
+                }
+                Action::Repeat => (),
+            },
+            WindowEvent::Key(Key::A, _, action, _) if opt.cocktail => match action {
+                Action::Press => {
+                    space_invaders.lock().unwrap().port2.insert(Port2::PLAYER_2_LEFT);
+                }
+                Action::Release => {
+                    space_invaders.lock().unwrap().port2.remove(Port2::PLAYER_2_LEFT);
+                }
+                Action::Repeat => (),
+            },
+            WindowEvent::Key(Key::D, _, action, _) if opt.cocktail => match action {
+                Action::Press => {
+                    space_invaders.lock().unwrap().port2.insert(Port2::PLAYER_2_RIGHT);
+                }
+                Action::Release => {
+                    space_invaders.lock().unwrap().port2.remove(Port2::PLAYER_2_RIGHT);
+                }
+                Action::Repeat => (),
+            },
+            WindowEvent::Key(Key::W, _, action, _) if opt.cocktail => match action {
+                Action::Press => {
+                    space_invaders.lock().unwrap().port2.insert(Port2::PLAYER_2_FIRE);
+                }
+                Action::Release => {
+                    space_invaders.lock().unwrap().port2.remove(Port2::PLAYER_2_FIRE);
                 }
                 Action::Repeat => (),
             },
@@ -372,46 +909,77 @@ fn process_input(
                 }
                 Action::Repeat => (),
             },
-            WindowEvent::Key(Key::F1, _, action, _) => match action {
-                Action::Press => {
+            WindowEvent::Key(key @ (Key::F1 | Key::F2 | Key::F3), _, Action::Press, _) => {
+                let index = match key {
+                    Key::F1 => 0,
+                    Key::F2 => 1,
+                    _ => 2,
+                };
+                if let Some(dip_switch) = opt.machine.dip_switches.get(index) {
                     let mut space_invaders = space_invaders.lock().unwrap();
-                    let mut bits = space_invaders.port2.bits();
-                    bits = (bits & 0b1111_1100) | (((bits & 0b0000_0011) + 1) % 4);
-                    space_invaders.port2 = unsafe { Port2::from_bits_unchecked(bits) };
-                    match space_invaders.port2.bits() & 0b0000_0011 {
-                        0 => info!("num of lives: 3"),
-                        1 => info!("num of lives: 4"),
-                        2 => info!("num of lives: 5"),
-                        3 => info!("num of lives: 6"),
-                        _ => unreachable!(),
+                    let mut dip_switches = space_invaders.dip_switches();
+                    let description = dip_switches.cycle(dip_switch);
+                    match space_invaders.set_dip_switches(dip_switches) {
+                        Ok(()) => {
+                            info!("{}: {description}", dip_switch.name);
+                            let name = String::from(opt.machine.name);
+                            config.dip_switches.insert(name, dip_switches);
+                            if let Err(err) = config.save() {
+                                warn!("cannot save the DIP switch settings: {err}");
+                            }
+                        }
+                        Err(err) => warn!("{err}"),
                     }
                 }
-                Action::Release | Action::Repeat => (),
-            },
-            WindowEvent::Key(Key::F2, _, action, _) => match action {
-                Action::Press => {
-                    let mut space_invaders = space_invaders.lock().unwrap();
-                    space_invaders.port2.toggle(Port2::EXTRA_LIFE_AT);
-                    if space_invaders.port2.contains(Port2::EXTRA_LIFE_AT) {
-                        info!("extra life at: 1000 points");
-                    } else {
-                        info!("extra life at: 1500 points");
-                    }
-                }
-                Action::Release | Action::Repeat => (),
-            },
-            WindowEvent::Key(Key::F3, _, action, _) => match action {
-                Action::Press => {
-                    let mut space_invaders = space_invaders.lock().unwrap();
-                    space_invaders.port2.toggle(Port2::PRICING_DISPLAY);
-                    if space_invaders.port2.contains(Port2::PRICING_DISPLAY) {
-                        info!("pricing display: off");
-                    } else {
-                        info!("pricing display: on");
-                    }
+            }
+            WindowEvent::Key(Key::M, _, Action::Press, _) => {
+                let mut space_invaders = space_invaders.lock().unwrap();
+                let mut volume = space_invaders.volume().clone();
+                volume.muted = !volume.muted;
+                info!("sound {}", if volume.muted { "muted" } else { "unmuted" });
+                set_volume(&mut space_invaders, volume, config);
+            }
+            WindowEvent::Key(
+                key @ (Key::Minus | Key::Equal),
+                _,
+                Action::Press | Action::Repeat,
+                modifiers,
+            ) => {
+                let step = match key {
+                    Key::Minus => -VOLUME_STEP,
+                    _ => VOLUME_STEP,
+                };
+                let mut space_invaders = space_invaders.lock().unwrap();
+                let mut volume = space_invaders.volume().clone();
+                if modifiers.contains(Modifiers::Shift) {
+                    let gain = volume.gains.entry(*selected_sound).or_insert(1.0);
+                    *gain = step_volume(*gain, step, MAX_SOUND_GAIN);
+                    info!("gain of {selected_sound:?}: {}%", percent(*gain));
+                } else {
+                    volume.master = step_volume(volume.master, step, 1.0);
+                    info!("master volume: {}%", percent(volume.master));
                 }
-                Action::Release | Action::Repeat => (),
+                set_volume(&mut space_invaders, volume, config);
+            }
+            WindowEvent::Key(key @ (Key::LeftBracket | Key::RightBracket), _, Action::Press, _) => {
+                let count = SoundId::ALL.len();
+                let index = SoundId::ALL.iter().position(|sound| sound == selected_sound).unwrap();
+                let index = match key {
+                    Key::LeftBracket => (index + count - 1) % count,
+                    _ => (index + 1) % count,
+                };
+                *selected_sound = SoundId::ALL[index];
+                let gain = config.volume.gains.get(selected_sound).copied().unwrap_or(1.0);
+                info!("selected {selected_sound:?}, at a gain of {}%", percent(gain));
+            }
+            WindowEvent::Key(Key::Backspace, _, action, _) => match action {
+                Action::Press => requests.rewind.store(true, Ordering::Relaxed),
+                Action::Release => requests.rewind.store(false, Ordering::Relaxed),
+                Action::Repeat => (),
             },
+            WindowEvent::Key(Key::F12, _, Action::Press, _) if opt.debug => {
+                requests.debug_break.store(true, Ordering::Relaxed);
+            }
             WindowEvent::FramebufferSize(_, _) => resized = true,
             WindowEvent::Close => return Ok(false),
             _ => (),
@@ -422,3 +990,69 @@ fn process_input(
     }
     Ok(true)
 }
+
+/// The step by which the hotkeys change the master volume and the gains of the sounds.
+const VOLUME_STEP: f32 = 0.1;
+/// The highest gain of a sound, which can make a quiet sample louder.
+const MAX_SOUND_GAIN: f32 = 2.0;
+
+/// Returns `volume` changed by `step`, rounded to a multiple of `VOLUME_STEP` and clamped to
+/// `0.0..=max`.
+fn step_volume(volume: f32, step: f32, max: f32) -> f32 {
+    (((volume + step) / VOLUME_STEP).round() * VOLUME_STEP).clamp(0.0, max)
+}
+
+fn percent(volume: f32) -> u32 {
+    (volume * 100.0).round() as u32
+}
+
+/// Applies `volume` to the machine and stores it in the config file.
+fn set_volume(space_invaders: &mut SpaceInvaders, volume: Volume, config: &mut Config) {
+    space_invaders.set_volume(volume.clone());
+    config.volume = volume;
+    if let Err(err) = config.save() {
+        warn!("cannot save the volume controls: {err}");
+    }
+}
+
+fn save_state_slot(key: Key) -> Option<u8> {
+    match key {
+        Key::Num0 => Some(0),
+        Key::Num1 => Some(1),
+        Key::Num2 => Some(2),
+        Key::Num3 => Some(3),
+        Key::Num4 => Some(4),
+        Key::Num5 => Some(5),
+        Key::Num6 => Some(6),
+        Key::Num7 => Some(7),
+        Key::Num8 => Some(8),
+        Key::Num9 => Some(9),
+        _ => None,
+    }
+}
+
+fn save_state(space_invaders: &Mutex<SpaceInvaders>, path: &Path) {
+    let result = File::create(path)
+        .map_err(space_invaders::Error::from)
+        .and_then(|file| space_invaders.lock().unwrap().save_state(BufWriter::new(file)));
+    match result {
+        Ok(()) => info!("saved state: '{}'", path.display()),
+        Err(err) => warn!("cannot save state to '{}': {err}", path.display()),
+    }
+}
+
+fn load_state(space_invaders: &Mutex<SpaceInvaders>, path: &Path) -> bool {
+    let result = File::open(path)
+        .map_err(space_invaders::Error::from)
+        .and_then(|file| space_invaders.lock().unwrap().load_state(BufReader::new(file)));
+    match result {
+        Ok(()) => {
+            info!("loaded state: '{}'", path.display());
+            true
+        }
+        Err(err) => {
+            warn!("cannot load state from '{}': {err}", path.display());
+            false
+        }
+    }
+}
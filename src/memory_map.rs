@@ -0,0 +1,139 @@
+//! The memory map of the board.
+//!
+//! The CPU of crate `i8080` sees one flat, fully writable memory, so the memory map is enforced
+//! around each instruction instead:
+//!
+//! - ROM written by an instruction is restored afterwards.
+//! - Mirrors of RAM are updated from RAM before an instruction reads them, and RAM is updated from
+//!   its mirrors after an instruction writes them.
+
+use log::warn;
+
+use crate::MemoryAccesses;
+
+const RAM_START: u16 = 0x2000;
+const RAM_MASK: u16 = 0x1FFF;
+const MIRROR_START: u16 = 0x4000;
+
+/// The ROM regions of a board, and the RAM at 0x2000-0x3FFF mirrored above 0x4000.
+pub(crate) struct MemoryMap {
+    roms: Vec<(u16, Vec<u8>)>,
+    log_rom_writes: bool,
+}
+
+impl MemoryMap {
+    /// Constructs a new `MemoryMap` whose ROM regions are `roms`, given by their start and length,
+    /// with the contents they have in `memory` now.
+    pub(crate) fn new(memory: &[u8], roms: &[(u16, u16)]) -> Self {
+        let roms = roms
+            .iter()
+            .map(|&(start, len)| {
+                let start_index = usize::from(start);
+                (start, memory[start_index..start_index + usize::from(len)].to_vec())
+            })
+            .collect();
+        Self { roms, log_rom_writes: false }
+    }
+
+    /// Returns the contents of the ROM regions, in the order given to [`MemoryMap::new`].
+    pub(crate) fn roms(&self) -> impl Iterator<Item = &[u8]> {
+        self.roms.iter().map(|(_, rom)| &rom[..])
+    }
+
+    /// Sets whether writes to ROM are logged as warnings.
+    pub(crate) fn set_log_rom_writes(&mut self, log_rom_writes: bool) {
+        self.log_rom_writes = log_rom_writes;
+    }
+
+    /// Prepares `memory` for an instruction that is going to make `accesses`.
+    pub(crate) fn before(&self, memory: &mut [u8], accesses: MemoryAccesses) {
+        for address in addresses(accesses.read) {
+            if let Some(ram_address) = self.mirrored_ram(address) {
+                memory[usize::from(address)] = memory[usize::from(ram_address)];
+            }
+        }
+    }
+
+    /// Undoes the effects of `accesses` that the board does not allow, after the instruction at
+    /// `pc` has made them in `memory`.
+    pub(crate) fn after(&self, memory: &mut [u8], accesses: MemoryAccesses, pc: u16) {
+        for address in addresses(accesses.write) {
+            if let Some(byte) = self.rom_byte(address) {
+                if self.log_rom_writes {
+                    warn!(
+                        "ignored a write to ROM at ${address:04X} by the instruction at ${pc:04X}"
+                    );
+                }
+                memory[usize::from(address)] = byte;
+            } else if let Some(ram_address) = self.mirrored_ram(address) {
+                memory[usize::from(ram_address)] = memory[usize::from(address)];
+            }
+        }
+    }
+
+    fn rom_byte(&self, address: u16) -> Option<u8> {
+        self.roms
+            .iter()
+            .find_map(|(start, rom)| rom.get(usize::from(address.wrapping_sub(*start))))
+            .copied()
+    }
+
+    /// Returns the address in RAM that `address` mirrors, if any.
+    fn mirrored_ram(&self, address: u16) -> Option<u16> {
+        (address >= MIRROR_START && self.rom_byte(address).is_none())
+            .then_some(RAM_START | (address & RAM_MASK))
+    }
+}
+
+fn addresses(range: Option<(u16, u16)>) -> impl Iterator<Item = u16> {
+    range.into_iter().flat_map(|(start, len)| (0..len).map(move |i| start.wrapping_add(i)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    #[test]
+    fn rom_writes_and_mirrors() {
+        // ROM at 0x0000-0x1FFF and 0x4000-0x47FF, as on Space Invaders Part II.
+        let mut memory = vec![0; 0x10000];
+        memory[0x4000] = 0x11;
+        let memory_map = MemoryMap::new(&memory, &[(0x0000, 0x2000), (0x4000, 0x800)]);
+
+        let write = |address| MemoryAccesses { read: None, write: Some((address, 1)) };
+        memory[0x1FFF] = 0xFF;
+        memory_map.after(&mut memory, write(0x1FFF), 0);
+        assert_eq!(memory[0x1FFF], 0);
+        memory[0x4000] = 0xFF;
+        memory_map.after(&mut memory, write(0x4000), 0);
+        assert_eq!(memory[0x4000], 0x11);
+        memory[0x4800] = 0x22;
+        memory_map.after(&mut memory, write(0x4800), 0);
+        assert_eq!(memory[0x2800], 0x22);
+        memory[0xFFFF] = 0x33;
+        memory_map.after(&mut memory, write(0xFFFF), 0);
+        assert_eq!(memory[0x3FFF], 0x33);
+
+        memory[0x2001] = 0x44;
+        memory_map.before(&mut memory, MemoryAccesses { read: Some((0xA000, 2)), write: None });
+        assert_eq!(&memory[0xA000..0xA002], [0, 0x44]);
+        // ROM is read as it is, even above 0x4000.
+        memory_map.before(&mut memory, MemoryAccesses { read: Some((0x4000, 1)), write: None });
+        assert_eq!(memory[0x4000], 0x11);
+    }
+
+    #[test]
+    fn board_memory() {
+        // MVI A,$AA; STA $0000; STA $4400; LDA $6410
+        let program = [0x3E, 0xAA, 0x32, 0x00, 0x00, 0x32, 0x00, 0x44, 0x3A, 0x10, 0x64];
+        let mut space_invaders = builder(&program).build().unwrap();
+        space_invaders.i8080.memory[0x2410] = 0x5A;
+        for _ in 0..4 {
+            space_invaders.update().unwrap();
+        }
+        assert_eq!(space_invaders.i8080.memory[0], 0x3E);
+        assert_eq!(space_invaders.i8080.memory[0x2400], 0xAA);
+        assert_eq!(space_invaders.i8080.cpu.a, 0x5A);
+    }
+}
@@ -0,0 +1,238 @@
+//! Input movies.
+//!
+//! A movie consists of a header followed by a record per frame, with multi-byte integers in
+//! little-endian order. The header consists of the magic bytes `SIVMOVIE`, a 16-bit format
+//! version, the number of ROM hashes as a byte, the CRC-32 checksum of each 2 KiB ROM bank, and
+//! the DIP switches of port 2, as defined by the machine. Each frame record consists of the values
+//! of ports 1 and 2 during the frame and the state hash at the end of the frame.
+
+use std::io::{self, Read, Write};
+
+use crate::{Error, FrameInfo, Port1, Port2, Result, SpaceInvaders};
+
+const MAGIC: &[u8; 8] = b"SIVMOVIE";
+const VERSION: u16 = 1;
+
+/// Records the inputs of a machine frame by frame, from power-on.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use space_invaders::{MovieRecorder, Port1, SpaceInvaders};
+///
+/// let mut space_invaders = SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?;
+/// let mut recorder = MovieRecorder::new(File::create("invaders.movie")?, &space_invaders)?;
+/// for frame in 0..600 {
+///     space_invaders.port1.set(Port1::COIN, frame == 300);
+///     recorder.run_frame(&mut space_invaders)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct MovieRecorder<W> {
+    writer: W,
+}
+
+impl<W: Write> MovieRecorder<W> {
+    /// Constructs a new `MovieRecorder` that writes to `writer` a movie of `space_invaders`, which
+    /// must not have run yet.
+    pub fn new(mut writer: W, space_invaders: &SpaceInvaders) -> Result<Self> {
+        if space_invaders.frame_count() != 0 {
+            return Err(Error::InvalidMovie { reason: "recording must start at power-on" });
+        }
+        let rom_hashes = space_invaders.rom_hashes();
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.push(rom_hashes.len() as u8);
+        for rom_hash in rom_hashes {
+            header.extend_from_slice(&rom_hash.to_le_bytes());
+        }
+        header.push(space_invaders.port2.bits() & space_invaders.machine().dip_switch_mask());
+        writer.write_all(&header)?;
+        Ok(Self { writer })
+    }
+
+    /// Runs `space_invaders` for a frame with its current inputs, and records them.
+    pub fn run_frame(&mut self, space_invaders: &mut SpaceInvaders) -> Result<FrameInfo> {
+        let (port1, port2) = (space_invaders.port1, space_invaders.port2);
+        let frame_info = space_invaders.run_frame()?;
+        let mut record = [0; 6];
+        record[0] = port1.bits();
+        record[1] = port2.bits();
+        record[2..].copy_from_slice(&space_invaders.state_hash().to_le_bytes());
+        self.writer.write_all(&record)?;
+        Ok(frame_info)
+    }
+}
+
+/// Plays back a movie recorded by [`MovieRecorder`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{fs::File, io::BufReader};
+/// use space_invaders::{MoviePlayer, SpaceInvaders};
+///
+/// let mut space_invaders = SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?;
+/// let file = BufReader::new(File::open("invaders.movie")?);
+/// let mut player = MoviePlayer::new(file, &mut space_invaders)?;
+/// while player.run_frame(&mut space_invaders)?.is_some() {}
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct MoviePlayer<R> {
+    reader: R,
+    frame: u64,
+}
+
+impl<R: Read> MoviePlayer<R> {
+    /// Constructs a new `MoviePlayer` that plays back a movie read from `reader` into
+    /// `space_invaders`, which must not have run yet.
+    ///
+    /// The DIP switches of `space_invaders` are set to those in the movie.
+    pub fn new(mut reader: R, space_invaders: &mut SpaceInvaders) -> Result<Self> {
+        if space_invaders.frame_count() != 0 {
+            return Err(Error::InvalidMovie { reason: "playback must start at power-on" });
+        }
+        let mut magic = [0; MAGIC.len()];
+        read_header(&mut reader, &mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidMovie { reason: "not a movie" });
+        }
+        let mut version = [0; 2];
+        read_header(&mut reader, &mut version)?;
+        if u16::from_le_bytes(version) != VERSION {
+            return Err(Error::InvalidMovie { reason: "unsupported version" });
+        }
+        let mut rom_hashes_len = [0];
+        read_header(&mut reader, &mut rom_hashes_len)?;
+        let mut rom_hashes = vec![0; usize::from(rom_hashes_len[0]) * 4];
+        read_header(&mut reader, &mut rom_hashes)?;
+        if !rom_hashes
+            .chunks(4)
+            .map(|rom_hash| u32::from_le_bytes(rom_hash.try_into().unwrap()))
+            .eq(space_invaders.rom_hashes().iter().copied())
+        {
+            return Err(Error::InvalidMovie { reason: "recorded with different ROMs" });
+        }
+        let mut dip_switches = [0];
+        read_header(&mut reader, &mut dip_switches)?;
+        let mask = space_invaders.machine().dip_switch_mask();
+        space_invaders.port2 = Port2::from_bits_truncate(
+            (space_invaders.port2.bits() & !mask) | (dip_switches[0] & mask),
+        );
+        Ok(Self { reader, frame: 0 })
+    }
+
+    /// Runs `space_invaders` for a frame with the inputs recorded in the movie.
+    ///
+    /// Returns `None` if the movie has ended, or an error if the state of `space_invaders` at the
+    /// end of the frame differs from the recorded one.
+    pub fn run_frame(&mut self, space_invaders: &mut SpaceInvaders) -> Result<Option<FrameInfo>> {
+        let mut record = [0; 6];
+        match self.reader.read(&mut record[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut record[1..]).map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => Error::InvalidMovie { reason: "truncated" },
+                _ => Error::from(err),
+            })?,
+        }
+        space_invaders.port1 = Port1::from_bits_truncate(record[0]);
+        space_invaders.port2 = Port2::from_bits_truncate(record[1]);
+        let frame_info = space_invaders.run_frame()?;
+        if space_invaders.state_hash() != u32::from_le_bytes(record[2..].try_into().unwrap()) {
+            return Err(Error::MovieDesync { frame: self.frame });
+        }
+        self.frame += 1;
+        Ok(Some(frame_info))
+    }
+
+    /// Returns the number of frames that have been played back.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::InvalidMovie { reason: "truncated header" },
+        _ => Error::from(err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    // IN 1; STA $2400; JMP $0000
+    const PROGRAM: [u8; 8] = [0xDB, 0x01, 0x32, 0x00, 0x24, 0xC3, 0x00, 0x00];
+
+    fn record() -> Vec<u8> {
+        let mut space_invaders = builder(&PROGRAM).build().unwrap();
+        let mut movie = Vec::new();
+        let mut recorder = MovieRecorder::new(&mut movie, &space_invaders).unwrap();
+        for frame in 0..10 {
+            space_invaders.port1.set(Port1::COIN, frame == 5);
+            recorder.run_frame(&mut space_invaders).unwrap();
+        }
+        movie
+    }
+
+    #[test]
+    fn state_hash() {
+        // INR A; JMP $0000
+        let mut space_invaders = builder(&[0x3C, 0xC3, 0x00, 0x00]).build().unwrap();
+        let state_hash = space_invaders.state_hash();
+        space_invaders.update().unwrap();
+        assert_ne!(space_invaders.state_hash(), state_hash);
+        let mut other = builder(&[0x3C, 0xC3, 0x00, 0x00]).build().unwrap();
+        other.update().unwrap();
+        assert_eq!(other.state_hash(), space_invaders.state_hash());
+    }
+
+    #[test]
+    fn playback() {
+        let movie = record();
+        let mut space_invaders = builder(&PROGRAM).build().unwrap();
+        let mut player = MoviePlayer::new(&movie[..], &mut space_invaders).unwrap();
+        while player.run_frame(&mut space_invaders).unwrap().is_some() {}
+        assert_eq!(player.frame(), 10);
+    }
+
+    #[test]
+    fn desync() {
+        let mut movie = record();
+        // The coin inserted at frame 5 is dropped from the recording, so the game reads other
+        // inputs than it did.
+        let header_len = MAGIC.len() + 2 + 1 + 4 * 4 + 1;
+        movie[header_len + 5 * 6] &= !Port1::COIN.bits();
+        let mut space_invaders = builder(&PROGRAM).build().unwrap();
+        let mut player = MoviePlayer::new(&movie[..], &mut space_invaders).unwrap();
+        for _ in 0..5 {
+            player.run_frame(&mut space_invaders).unwrap();
+        }
+        assert!(matches!(
+            player.run_frame(&mut space_invaders),
+            Err(Error::MovieDesync { frame: 5 })
+        ));
+    }
+
+    #[test]
+    fn different_roms() {
+        let movie = record();
+        let mut space_invaders = builder(&[0xC3, 0x00, 0x00]).build().unwrap();
+        assert!(matches!(
+            MoviePlayer::new(&movie[..], &mut space_invaders),
+            Err(Error::InvalidMovie { reason: "recorded with different ROMs" })
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+//! A rewind buffer.
+//!
+//! Only the most recent save state is kept in full. Each older save state is stored as the
+//! difference from its successor, XORed byte by byte and then run-length encoded. Consecutive
+//! frames of Space Invaders differ in a few hundred bytes at most, so a difference usually takes a
+//! tiny fraction of the size of a save state.
+
+use std::collections::VecDeque;
+
+use crate::{Result, SpaceInvaders};
+
+/// A ring buffer of save states taken frame by frame, which can be used to step a machine
+/// backwards.
+///
+/// # Example
+///
+/// ```no_run
+/// use space_invaders::{RewindBuffer, SpaceInvaders};
+///
+/// let mut space_invaders = SpaceInvaders::new(
+///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+///     None::<[&str; 10]>,
+///     None,
+/// )?;
+/// let mut rewind_buffer = RewindBuffer::new(60 * 60); // 60 seconds
+/// for _ in 0..600 {
+///     space_invaders.run_frame()?;
+///     rewind_buffer.push(&space_invaders)?;
+/// }
+/// // Go back 5 seconds.
+/// for _ in 0..300 {
+///     rewind_buffer.rewind(&mut space_invaders)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct RewindBuffer {
+    capacity: usize,
+    latest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Constructs a new `RewindBuffer` that can step back up to `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, latest: Vec::new(), deltas: VecDeque::new() }
+    }
+
+    /// Returns the number of frames that the buffer can step back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Returns `true` if the buffer cannot step back.
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Discards all the save states in the buffer.
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.deltas.clear();
+    }
+
+    /// Takes a save state of `space_invaders` and appends it to the buffer, discarding the oldest
+    /// one if the buffer is full.
+    pub fn push(&mut self, space_invaders: &SpaceInvaders) -> Result<()> {
+        let mut state = Vec::with_capacity(self.latest.len());
+        space_invaders.save_state(&mut state)?;
+        if !self.latest.is_empty() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            if self.capacity > 0 {
+                self.deltas.push_back(encode_delta(&state, &self.latest));
+            }
+        }
+        self.latest = state;
+        Ok(())
+    }
+
+    /// Restores `space_invaders` to the save state preceding the most recent one, and makes it the
+    /// most recent one.
+    ///
+    /// Returns `false`, leaving `space_invaders` untouched, if there is no preceding save state.
+    pub fn rewind(&mut self, space_invaders: &mut SpaceInvaders) -> Result<bool> {
+        match self.deltas.pop_back() {
+            Some(delta) => {
+                apply_delta(&delta, &mut self.latest);
+                space_invaders.load_state(&self.latest[..])?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Encodes `a ^ b` as a sequence of runs, each of which consists of the number of zero bytes, the
+/// number of literal bytes, and the literal bytes.
+fn encode_delta(a: &[u8], b: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(a.len(), b.len());
+    let mut delta = Vec::new();
+    let mut i = 0;
+    while i < a.len() {
+        let zeros_start = i;
+        while i < a.len() && a[i] == b[i] {
+            i += 1;
+        }
+        let literals_start = i;
+        // Two or fewer equal bytes in a row are cheaper to store as literals than as a new run.
+        while i < a.len()
+            && (a[i] != b[i] || a[i..].iter().zip(&b[i..]).take(3).any(|(a, b)| a != b))
+        {
+            i += 1;
+        }
+        write_varint(&mut delta, literals_start - zeros_start);
+        write_varint(&mut delta, i - literals_start);
+        delta.extend(a[literals_start..i].iter().zip(&b[literals_start..i]).map(|(a, b)| a ^ b));
+    }
+    delta
+}
+
+fn apply_delta(delta: &[u8], state: &mut [u8]) {
+    let mut delta = delta;
+    let mut i = 0;
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let literals_len = read_varint(&mut delta);
+        let (literals, rest) = delta.split_at(literals_len);
+        state[i..i + literals_len].iter_mut().zip(literals).for_each(|(byte, xor)| *byte ^= xor);
+        i += literals_len;
+        delta = rest;
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf[0];
+        *buf = &buf[1..];
+        value |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::builder;
+
+    #[test]
+    fn delta() {
+        let a: Vec<u8> = (0..1000).map(|i| (i * 7 % 256) as u8).collect();
+        let mut b = a.clone();
+        // A single byte, two bytes with an equal one between them, and a run past 127 bytes.
+        b[0] ^= 0xFF;
+        b[300] ^= 1;
+        b[302] ^= 2;
+        b[500..700].iter_mut().for_each(|byte| *byte = !*byte);
+        let delta = encode_delta(&a, &b);
+        assert!(delta.len() < 250);
+        let mut state = b.clone();
+        apply_delta(&delta, &mut state);
+        assert_eq!(state, a);
+        assert!(encode_delta(&a, &a).len() <= 3);
+    }
+
+    #[test]
+    fn rewind() {
+        // INR A; STA $2400; JMP $0000
+        let mut space_invaders =
+            builder(&[0x3C, 0x32, 0x00, 0x24, 0xC3, 0x00, 0x00]).build().unwrap();
+        let mut rewind_buffer = RewindBuffer::new(5);
+        let mut state_hashes = Vec::new();
+        for _ in 0..8 {
+            space_invaders.run_frame().unwrap();
+            rewind_buffer.push(&space_invaders).unwrap();
+            state_hashes.push(space_invaders.state_hash());
+        }
+        assert_eq!(rewind_buffer.len(), 5);
+        for state_hash in state_hashes.iter().rev().skip(1).take(5) {
+            assert!(rewind_buffer.rewind(&mut space_invaders).unwrap());
+            assert_eq!(space_invaders.state_hash(), *state_hash);
+        }
+        assert!(rewind_buffer.is_empty());
+        assert!(!rewind_buffer.rewind(&mut space_invaders).unwrap());
+        assert_eq!(space_invaders.state_hash(), state_hashes[2]);
+    }
+}
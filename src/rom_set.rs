@@ -0,0 +1,243 @@
+//! Verification of ROM sets against the known sizes and hashes of their ROMs.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io, iter,
+    path::PathBuf,
+};
+
+use crate::{
+    file_set::FileSet,
+    machine::{Machine, Rom},
+};
+
+/// What was found for a ROM of a machine, as returned by [`verify`].
+#[derive(Debug)]
+pub enum RomStatus {
+    /// A good dump at `path`, which is the name of the ROM or one of its aliases.
+    Good { path: PathBuf },
+    /// A good dump at `path`, whose file name is neither the name of the ROM nor an alias.
+    Misnamed { path: PathBuf },
+    /// A file of `len` bytes at `path`, which is not the size of the ROM.
+    WrongSize { path: PathBuf, len: usize },
+    /// A file of the right size at `path`, whose hashes are not those of a good dump.
+    BadHash { path: PathBuf, crc32: u32, sha1: String },
+    /// No file under the name of the ROM or any of its aliases.
+    Missing,
+}
+
+/// The result of checking a ROM of a machine.
+#[derive(Debug)]
+pub struct RomCheck {
+    /// The ROM.
+    pub rom: &'static Rom,
+    /// What was found for it.
+    pub status: RomStatus,
+    bytes: Vec<u8>,
+}
+
+impl RomCheck {
+    /// Returns `true` if a good dump has been found under the name of the ROM or an alias.
+    pub fn is_good(&self) -> bool {
+        matches!(self.status, RomStatus::Good { .. })
+    }
+
+    /// Returns the contents of the file that can be loaded for the ROM, if any. A bad dump of
+    /// the right size can be loaded, although the game may not run correctly.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match &self.status {
+            RomStatus::Good { .. } | RomStatus::Misnamed { .. } | RomStatus::BadHash { .. } => {
+                Some(&self.bytes)
+            }
+            RomStatus::WrongSize { .. } | RomStatus::Missing => None,
+        }
+    }
+}
+
+impl Display for RomCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = self.rom.name;
+        match &self.status {
+            RomStatus::Good { path } if self.rom.hashes.is_none() => {
+                write!(f, "{name}: ok, size only ('{}')", path.display())
+            }
+            RomStatus::Good { path } => write!(f, "{name}: ok ('{}')", path.display()),
+            RomStatus::Misnamed { path } => {
+                write!(f, "{name}: misnamed, found as '{}'", path.display())
+            }
+            RomStatus::WrongSize { path, len } => write!(
+                f,
+                "{name}: wrong size, {len} bytes instead of {} ('{}')",
+                self.rom.len,
+                path.display()
+            ),
+            RomStatus::BadHash { path, crc32, sha1 } => {
+                write!(f, "{name}: bad dump, CRC32 {crc32:08x} SHA-1 {sha1} ('{}')", path.display())
+            }
+            RomStatus::Missing if self.rom.aliases.is_empty() => write!(f, "{name}: missing"),
+            RomStatus::Missing => {
+                write!(f, "{name}: missing, also looked for {}", self.rom.aliases.join(", "))
+            }
+        }
+    }
+}
+
+/// Looks for the ROMs of `machine` in `files` and checks them.
+///
+/// A ROM is looked for under its name and then under its aliases. If no good dump is found
+/// there, but the hashes of the ROM are known and another file in `files` has them, that file is
+/// reported as misnamed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use space_invaders::{file_set::FileSet, machine, rom_set};
+///
+/// let files = FileSet::open(Path::new("roms"), "invaders")?;
+/// for check in rom_set::verify(&machine::INVADERS, &files)? {
+///     println!("{check}");
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn verify(machine: &'static Machine, files: &FileSet) -> io::Result<Vec<RomCheck>> {
+    // The files small enough to be ROMs, read only if a ROM has to be searched for.
+    let mut other_files = None;
+    let mut checks = Vec::with_capacity(machine.roms.len());
+    for rom in machine.roms {
+        let found = find(rom, files)?;
+        let (status, bytes) = match found {
+            Some((path, bytes)) if rom.matches(&bytes) => (RomStatus::Good { path }, bytes),
+            found => {
+                let misnamed = match rom.hashes {
+                    Some(_) => {
+                        if other_files.is_none() {
+                            other_files = Some(files.read_all(0x10000)?);
+                        }
+                        other_files.iter().flatten().find(|(_, bytes)| rom.matches(bytes))
+                    }
+                    None => None,
+                };
+                match (misnamed, found) {
+                    (Some((name, bytes)), _) => {
+                        (RomStatus::Misnamed { path: files.path(name) }, bytes.clone())
+                    }
+                    (None, Some((path, bytes))) if bytes.len() != usize::from(rom.len) => {
+                        (RomStatus::WrongSize { path, len: bytes.len() }, bytes)
+                    }
+                    (None, Some((path, bytes))) => {
+                        let crc32 = crc32fast::hash(&bytes);
+                        let sha1 = sha1_smol::Sha1::from(&bytes).digest().to_string();
+                        (RomStatus::BadHash { path, crc32, sha1 }, bytes)
+                    }
+                    (None, None) => (RomStatus::Missing, Vec::new()),
+                }
+            }
+        };
+        checks.push(RomCheck { rom, status, bytes });
+    }
+    Ok(checks)
+}
+
+/// Reads the file for `rom` in `files`, under its name or the first alias found.
+fn find(rom: &Rom, files: &FileSet) -> io::Result<Option<(PathBuf, Vec<u8>)>> {
+    for name in iter::once(&rom.name).chain(rom.aliases) {
+        if let Some(bytes) = files.read(name)? {
+            return Ok(Some((files.path(name), bytes)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        machine::{InputPorts, RomHashes, ShifterPorts},
+        UnmappedIoPolicy,
+    };
+
+    const fn test_rom(
+        name: &'static str,
+        aliases: &'static [&'static str],
+        crc32: u32,
+        sha1: &'static str,
+    ) -> Rom {
+        Rom { name, aliases, address: 0, len: 4, hashes: Some(RomHashes { crc32, sha1 }) }
+    }
+
+    static MACHINE: Machine = Machine {
+        name: "test",
+        description: "Test",
+        roms: &[
+            // [1, 2, 3, 4]
+            test_rom("good.bin", &[], 0xb63c_fbcd, "12dada1fff4d4787ade3333147202c3b443e376f"),
+            // [5, 6, 7, 8]
+            test_rom("renamed.bin", &[], 0x538d_4d69, "85d9651d9a399a67e015d047fd9e6a941e6b20bb"),
+            // [9, 9, 9, 9]
+            test_rom("short.bin", &[], 0xfb83_a8f4, "e0bafe976d92fbad43d8806b0e6d3460192e10aa"),
+            // [10, 11, 12, 13]
+            test_rom("bad.bin", &[], 0x90a8_e328, "22a4085137435f513d56f326230c3ad7733f8203"),
+            // [14, 15, 16, 17]
+            test_rom(
+                "missing.bin",
+                &["missing.alt"],
+                0xeab5_ddb1,
+                "dba68e80f09b48e457443661c5068c148083bdfe",
+            ),
+            Rom {
+                name: "unhashed.bin",
+                aliases: &["unhashed.alt"],
+                address: 0,
+                len: 4,
+                hashes: None,
+            },
+        ],
+        inputs: InputPorts { port1: 1, port2: 2 },
+        dip_switches: &[],
+        sound_ports: [3, 5],
+        sounds: &[],
+        shifter: ShifterPorts { amount: 2, data: 4, result: 3 },
+        watchdog_port: 6,
+        unmapped_io_policy: UnmappedIoPolicy::Fail,
+        high_score: None,
+    };
+
+    #[test]
+    fn verify_classifies_roms() {
+        let entries = [
+            ("good.bin", vec![1, 2, 3, 4]),
+            ("other.bin", vec![5, 6, 7, 8]),
+            ("short.bin", vec![9, 9, 9]),
+            ("bad.bin", vec![10, 11, 12, 0]),
+            ("unhashed.alt", vec![0; 4]),
+        ];
+        let entries = entries.into_iter().map(|(name, bytes)| (name.to_string(), bytes)).collect();
+        let files = FileSet::Zip { path: PathBuf::from("test.zip"), entries };
+        let checks = verify(&MACHINE, &files).unwrap();
+        assert_eq!(checks.len(), 6);
+
+        assert!(
+            matches!(&checks[0].status, RomStatus::Good { path } if path.ends_with("good.bin"))
+        );
+        assert_eq!(checks[0].bytes(), Some(&[1, 2, 3, 4][..]));
+        assert!(
+            matches!(&checks[1].status, RomStatus::Misnamed { path } if path.ends_with("other.bin"))
+        );
+        assert_eq!(checks[1].bytes(), Some(&[5, 6, 7, 8][..]));
+        assert!(matches!(checks[2].status, RomStatus::WrongSize { len: 3, .. }));
+        assert_eq!(checks[2].bytes(), None);
+        match &checks[3].status {
+            RomStatus::BadHash { crc32, sha1, .. } => {
+                assert_eq!(*crc32, crc32fast::hash(&[10, 11, 12, 0]));
+                assert_eq!(*sha1, sha1_smol::Sha1::from([10, 11, 12, 0]).digest().to_string());
+            }
+            status => panic!("{status:?}"),
+        }
+        assert_eq!(checks[3].bytes(), Some(&[10, 11, 12, 0][..]));
+        assert!(matches!(checks[4].status, RomStatus::Missing));
+        assert_eq!(checks[4].to_string(), "missing.bin: missing, also looked for missing.alt");
+        assert!(checks[5].is_good());
+        assert_eq!(checks[5].to_string(), "unhashed.bin: ok, size only ('test.zip/unhashed.alt')");
+    }
+}
@@ -0,0 +1,286 @@
+//! Sample sets: the samples of the sounds, found under their numbers, their names or the file
+//! names given by a manifest.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{file_set::FileSet, machine::SoundId, Error, Result};
+
+/// The name of the manifest that [`read`] looks for in a sample set.
+pub const MANIFEST_NAME: &str = "samples.toml";
+
+/// The extensions of the files looked for under the numbers and the names of the sounds.
+const EXTENSIONS: [&str; 3] = ["wav", "ogg", "flac"];
+
+/// A sample of a sound, with how it is played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// The contents of a WAV, Ogg Vorbis or FLAC file.
+    pub bytes: Vec<u8>,
+    /// The gain that the sample is played with, 1.0 for its own volume.
+    pub volume: f32,
+    /// Whether the sample repeats until its sound bit is cleared, instead of playing once, or
+    /// `None` to loop it if the machine does.
+    pub looping: Option<bool>,
+}
+
+impl From<Vec<u8>> for Sample {
+    fn from(bytes: Vec<u8>) -> Self {
+        Sample { bytes, volume: 1.0, looping: None }
+    }
+}
+
+/// A manifest that maps the sounds to the files of a sample set, as stored in TOML.
+///
+/// # Example
+///
+/// ```
+/// use space_invaders::{machine::SoundId, sample_set::Manifest};
+///
+/// let manifest: Manifest = r#"
+///     shoot = { file = "laser.ogg", volume = 0.8 }
+///     ufo_low_pitch = { file = "saucer.flac", volume = 0.4, loop = true }
+/// "#
+/// .parse()?;
+/// assert_eq!(manifest.sounds[&SoundId::Shoot].file, "laser.ogg");
+/// assert_eq!(manifest.sounds[&SoundId::UfoLowPitch].looping, Some(true));
+/// # Ok::<(), space_invaders::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Manifest {
+    /// The entries of the sounds, by the names of the sounds in snake case, such as `shoot` or
+    /// `fast_invader1`.
+    pub sounds: BTreeMap<SoundId, ManifestEntry>,
+}
+
+/// The entry of a sound in a [`Manifest`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// The name of the file in the sample set.
+    pub file: String,
+    /// The gain that the sample is played with, 1.0 by default.
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    /// Whether the sample repeats until its sound bit is cleared, which defaults to whether the
+    /// machine loops the sound.
+    #[serde(default, rename = "loop", skip_serializing_if = "Option::is_none")]
+    pub looping: Option<bool>,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+impl FromStr for Manifest {
+    type Err = Error;
+
+    /// Parses a manifest from TOML. Fails with `Error::InvalidSampleManifest` if a volume is
+    /// negative or not finite.
+    fn from_str(s: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(s)?;
+        if manifest.sounds.values().any(|entry| !entry.volume.is_finite() || entry.volume < 0.0) {
+            return Err(Error::InvalidSampleManifest { reason: "negative or non-finite volume" });
+        }
+        Ok(manifest)
+    }
+}
+
+/// Reads the samples of the sounds from `files`, in the order of `SoundId::ALL`.
+///
+/// The file of a sound is the one that `manifest` maps it to if any, and else the first found
+/// under the number of the sound, as in `{0..9}.wav`, or under one of its names, such as
+/// `shot.wav` or `ufo_lp.wav`, with the extension `wav`, `ogg` or `flac`. Without `manifest`,
+/// the manifest `samples.toml` of `files` is used if there is one. A sound whose file is missing
+/// is `None`, with a warning, so that it is synthesized.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use space_invaders::{file_set::FileSet, sample_set};
+///
+/// let files = FileSet::open(Path::new("samples"), "invaders")?;
+/// let samples = sample_set::read(&files, None)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn read(files: &FileSet, manifest: Option<&Manifest>) -> Result<[Option<Sample>; 10]> {
+    let found_manifest = match manifest {
+        Some(_) => None,
+        None => match files.read(MANIFEST_NAME)? {
+            Some(bytes) => Some(String::from_utf8_lossy(&bytes).parse::<Manifest>()?),
+            None => None,
+        },
+    };
+    let manifest = manifest.or(found_manifest.as_ref());
+    let mut samples: [Option<Sample>; 10] = Default::default();
+    for (sound, sample) in SoundId::ALL.into_iter().zip(&mut samples) {
+        match manifest.and_then(|manifest| manifest.sounds.get(&sound)) {
+            Some(entry) => {
+                *sample = files.read(&entry.file)?.map(|bytes| Sample {
+                    bytes,
+                    volume: entry.volume,
+                    looping: entry.looping,
+                });
+                if sample.is_none() {
+                    warn!(
+                        "sample not found, synthesizing it: '{}'",
+                        files.path(&entry.file).display()
+                    );
+                }
+            }
+            None => {
+                *sample = find(sound, files)?.map(Sample::from);
+                if sample.is_none() {
+                    let name = format!("{}.wav", sound as u8);
+                    warn!("sample not found, synthesizing it: '{}'", files.path(&name).display());
+                }
+            }
+        }
+    }
+    Ok(samples)
+}
+
+/// Reads the file of `sound` in `files`, under its number or the first of its names found.
+fn find(sound: SoundId, files: &FileSet) -> Result<Option<Vec<u8>>> {
+    let number = (sound as u8).to_string();
+    for name in [number.as_str()].into_iter().chain(names(sound).iter().copied()) {
+        for extension in EXTENSIONS {
+            if let Some(bytes) = files.read(&format!("{name}.{extension}"))? {
+                return Ok(Some(bytes));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the names of the sample of `sound` in named sample sets.
+fn names(sound: SoundId) -> &'static [&'static str] {
+    match sound {
+        SoundId::UfoHighPitch => &["ufo_hp", "ufo_highpitch"],
+        SoundId::Shoot => &["shot", "shoot"],
+        SoundId::Explosion => &["basehit", "explosion"],
+        SoundId::InvaderKilled => &["invhit", "invaderkilled"],
+        SoundId::FastInvader1 => &["walk1", "fastinvader1"],
+        SoundId::FastInvader2 => &["walk2", "fastinvader2"],
+        SoundId::FastInvader3 => &["walk3", "fastinvader3"],
+        SoundId::FastInvader4 => &["walk4", "fastinvader4"],
+        SoundId::UfoLowPitch => &["ufo_lp", "ufo_lowpitch"],
+        SoundId::ExtendedPlay => &["extend", "extendedplay"],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::PathBuf};
+
+    use rodio::{Decoder, Source};
+
+    use super::*;
+
+    fn files(entries: &[(&str, &[u8])]) -> FileSet {
+        let entries = entries.iter().map(|(name, bytes)| (name.to_string(), bytes.to_vec()));
+        FileSet::Zip { path: PathBuf::from("invaders.zip"), entries: entries.collect() }
+    }
+
+    fn bytes(samples: &[Option<Sample>; 10], sound: SoundId) -> Option<&[u8]> {
+        samples[sound as usize].as_ref().map(|sample| sample.bytes.as_slice())
+    }
+
+    #[test]
+    fn find_by_name() {
+        let files = files(&[
+            ("0.wav", b"0"),
+            ("ufo_hp.wav", b"ufo_hp"),
+            ("shoot.wav", b"shoot"),
+            ("shot.ogg", b"shot"),
+            ("ufo_lowpitch.flac", b"ufo_lowpitch"),
+        ]);
+        let samples = read(&files, None).unwrap();
+        // The number of a sound comes before its names, which come in order whatever their
+        // extensions.
+        assert_eq!(bytes(&samples, SoundId::UfoHighPitch), Some(&b"0"[..]));
+        assert_eq!(bytes(&samples, SoundId::Shoot), Some(&b"shot"[..]));
+        assert_eq!(bytes(&samples, SoundId::UfoLowPitch), Some(&b"ufo_lowpitch"[..]));
+        assert_eq!(bytes(&samples, SoundId::Explosion), None);
+        assert_eq!(samples[SoundId::Shoot as usize].as_ref().unwrap().volume, 1.0);
+    }
+
+    #[test]
+    fn manifest() {
+        let files = files(&[
+            ("1.wav", b"1"),
+            ("2.wav", b"2"),
+            ("3.wav", b"3"),
+            ("laser.ogg", b"laser"),
+            (
+                MANIFEST_NAME,
+                br#"
+                    shoot = { file = "laser.ogg", volume = 0.5, loop = true }
+                    explosion = { file = "boom.wav" }
+                "#,
+            ),
+        ]);
+        let samples = read(&files, None).unwrap();
+        assert_eq!(
+            samples[SoundId::Shoot as usize],
+            Some(Sample { bytes: b"laser".to_vec(), volume: 0.5, looping: Some(true) })
+        );
+        // A sound mapped to a missing file is synthesized rather than found under its number.
+        assert_eq!(samples[SoundId::Explosion as usize], None);
+        assert_eq!(bytes(&samples, SoundId::InvaderKilled), Some(&b"3"[..]));
+
+        // The manifest given replaces that of the sample set.
+        let manifest: Manifest = r#"invader_killed = { file = "1.wav" }"#.parse().unwrap();
+        let samples = read(&files, Some(&manifest)).unwrap();
+        assert_eq!(bytes(&samples, SoundId::Shoot), Some(&b"1"[..]));
+        assert_eq!(bytes(&samples, SoundId::Explosion), Some(&b"2"[..]));
+        assert_eq!(bytes(&samples, SoundId::InvaderKilled), Some(&b"1"[..]));
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        for manifest in [
+            r#"shoot = { file = "shot.wav", volume = -0.5 }"#,
+            r#"shoot = { file = "shot.wav", volume = nan }"#,
+            r#"shoot = { file = "shot.wav", volume = inf }"#,
+        ] {
+            assert!(matches!(
+                manifest.parse::<Manifest>(),
+                Err(Error::InvalidSampleManifest { .. })
+            ));
+            let files = files(&[(MANIFEST_NAME, manifest.as_bytes())]);
+            assert!(matches!(read(&files, None), Err(Error::InvalidSampleManifest { .. })));
+        }
+        for manifest in [
+            r#"laser = { file = "shot.wav" }"#,
+            r#"shoot = { file = "shot.wav", pitch = 2.0 }"#,
+            r#"shoot = { volume = 0.5 }"#,
+            r#"shoot = "shot.wav""#,
+        ] {
+            assert!(matches!(manifest.parse::<Manifest>(), Err(Error::Toml { .. })));
+        }
+    }
+
+    #[test]
+    fn decode() {
+        // A 440 Hz tone at half the full scale, of 2205 samples at 44.1 kHz.
+        let files = files(&[
+            ("shot.ogg", include_bytes!("../tests/data/tone.ogg")),
+            ("basehit.flac", include_bytes!("../tests/data/tone.flac")),
+        ]);
+        let samples = read(&files, None).unwrap();
+        for sound in [SoundId::Shoot, SoundId::Explosion] {
+            let bytes = bytes(&samples, sound).unwrap().to_vec();
+            let decoder = Decoder::new(Cursor::new(bytes)).unwrap();
+            assert_eq!((decoder.channels(), decoder.sample_rate()), (1, 44_100));
+            let tone: Vec<i16> = decoder.collect();
+            assert!(tone.len() >= 2205, "{sound:?}: {} samples", tone.len());
+            let peak = tone.iter().map(|x| x.unsigned_abs()).max().unwrap();
+            assert!((15_000..=17_500).contains(&peak), "{sound:?}: peak {peak}");
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Save states of the whole machine.
+//!
+//! A save state consists of the following, with multi-byte integers in little-endian order:
+//!
+//! * the magic bytes `SIVSTATE`, a 16-bit format version and the name of the machine, such as
+//!   `invaders`, preceded by its length in a byte,
+//! * the registers A, B, C, D, E, H and L, the condition flags, SP, PC and the interrupt enable
+//!   flip-flop of the CPU,
+//! * the 64 KiB memory,
+//! * ports 1 and 2 and the two sound ports,
+//! * the shift register and the shift offset of the video shifter,
+//! * the frame count, the states executed in the current frame, the number of interrupts raised
+//!   in it and the RST instruction of the interrupt held for the CPU, or 0 if none is held,
+//! * the number of frames since the game last wrote the watchdog port, and
+//! * the CRC-32 checksum of all the preceding bytes.
+
+use std::io::{Read, Write};
+
+use i8080::ConditionFlags;
+
+use crate::{Error, Port1, Port2, Result, SpaceInvaders};
+
+const MAGIC: &[u8; 8] = b"SIVSTATE";
+const VERSION: u16 = 3;
+const MEMORY_LEN: usize = 0x10000;
+/// The length of a save state, without the name of the machine.
+const LEN: usize = MAGIC.len() + 2 + 1 + 13 + MEMORY_LEN + 4 + 3 + 14 + 1 + 4;
+
+impl SpaceInvaders {
+    /// Writes a save state of the whole machine to `writer`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use space_invaders::SpaceInvaders;
+    ///
+    /// let mut space_invaders = SpaceInvaders::new(
+    ///     &["invaders.h", "invaders.g", "invaders.f", "invaders.e"],
+    ///     None::<[&str; 10]>,
+    ///     None,
+    /// )?;
+    /// space_invaders.run_frame()?;
+    /// space_invaders.save_state(File::create("invaders.state")?)?;
+    /// space_invaders.load_state(File::open("invaders.state")?)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn save_state<W: Write>(&self, mut writer: W) -> Result<()> {
+        let name = self.machine.name.as_bytes();
+        let mut state = Vec::with_capacity(LEN + name.len());
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&VERSION.to_le_bytes());
+        state.push(name.len() as u8);
+        state.extend_from_slice(name);
+
+        let cpu = &self.i8080.cpu;
+        state.extend_from_slice(&[
+            cpu.a,
+            cpu.b,
+            cpu.c,
+            cpu.d,
+            cpu.e,
+            cpu.h,
+            cpu.l,
+            cpu.condition_flags.bits(),
+        ]);
+        state.extend_from_slice(&cpu.sp.to_le_bytes());
+        state.extend_from_slice(&cpu.pc.to_le_bytes());
+        state.push(u8::from(cpu.interrupt_enabled));
+        state.extend_from_slice(&self.i8080.memory[..MEMORY_LEN]);
+
+        state.extend_from_slice(&[
+            self.port1.bits(),
+            self.port2.bits(),
+            self.sound_latches[0],
+            self.sound_latches[1],
+        ]);
+        state.extend_from_slice(&self.video_shifter.register.to_le_bytes());
+        state.push(self.video_shifter.offset);
+
+        let scheduler = &self.interrupt_scheduler;
+        state.extend_from_slice(&scheduler.frame_count.to_le_bytes());
+        state.extend_from_slice(&scheduler.frame_states.to_le_bytes());
+        state.push(scheduler.raised);
+        state.push(scheduler.held.map_or(0, |rst| 0xC7 | (rst << 3)));
+        state.push(self.watchdog_frames);
+
+        let checksum = crc32fast::hash(&state);
+        state.extend_from_slice(&checksum.to_le_bytes());
+        writer.write_all(&state)?;
+        Ok(())
+    }
+
+    /// Restores the whole machine from a save state read from `reader`.
+    ///
+    /// The machine is left untouched if the save state is invalid or has been saved by another
+    /// machine.
+    pub fn load_state<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        let mut state = Vec::with_capacity(LEN + self.machine.name.len());
+        reader.read_to_end(&mut state)?;
+        if state.len() < MAGIC.len() || &state[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidSaveState { reason: "not a save state" });
+        }
+        if state.len() < MAGIC.len() + 2 {
+            return Err(Error::InvalidSaveState { reason: "truncated" });
+        }
+        if u16::from_le_bytes([state[8], state[9]]) != VERSION {
+            return Err(Error::InvalidSaveState { reason: "unsupported version" });
+        }
+        let name_len = state.get(MAGIC.len() + 2).map_or(0, |&len| usize::from(len));
+        if state.len() != LEN + name_len {
+            return Err(Error::InvalidSaveState { reason: "truncated" });
+        }
+        let (state, checksum) = state.split_at(LEN + name_len - 4);
+        if crc32fast::hash(state) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(Error::InvalidSaveState { reason: "checksum mismatch" });
+        }
+
+        let mut reader = StateReader { state, position: MAGIC.len() + 3 };
+        if reader.slice(name_len) != self.machine.name.as_bytes() {
+            return Err(Error::InvalidSaveState { reason: "saved by another machine" });
+        }
+        let [a, b, c, d, e, h, l, condition_flags] = reader.bytes::<8>();
+        let sp = u16::from_le_bytes(reader.bytes());
+        let pc = u16::from_le_bytes(reader.bytes());
+        let [interrupt_enabled] = reader.bytes();
+        let memory = reader.slice(MEMORY_LEN);
+        let [port1, port2, sound_port_1, sound_port_2] = reader.bytes();
+        let register = u16::from_le_bytes(reader.bytes());
+        let [offset] = reader.bytes();
+        let frame_count = u64::from_le_bytes(reader.bytes());
+        let frame_states = u32::from_le_bytes(reader.bytes());
+        let [raised, held] = reader.bytes();
+        let [watchdog_frames] = reader.bytes();
+
+        let cpu = &mut self.i8080.cpu;
+        (cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l) = (a, b, c, d, e, h, l);
+        cpu.condition_flags = ConditionFlags::from_bits_truncate(condition_flags);
+        cpu.sp = sp;
+        cpu.pc = pc;
+        cpu.interrupt_enabled = interrupt_enabled != 0;
+        self.i8080.memory[..MEMORY_LEN].copy_from_slice(memory);
+
+        self.port1 = Port1::from_bits_truncate(port1);
+        self.port2 = Port2::from_bits_truncate(port2);
+        self.video_shifter.register = register;
+        self.video_shifter.offset = offset;
+
+        self.interrupt_scheduler.frame_count = frame_count;
+        self.interrupt_scheduler.frame_states = frame_states;
+        self.interrupt_scheduler.raised = raised;
+        self.interrupt_scheduler.held = (held != 0).then_some((held >> 3) & 0b111);
+        self.watchdog_frames = watchdog_frames;
+        // The sounds are handled at the cycle of the state.
+        self.jump_sound_latches([sound_port_1, sound_port_2]);
+        Ok(())
+    }
+}
+
+struct StateReader<'a> {
+    state: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn slice(&mut self, len: usize) -> &'a [u8] {
+        let slice = &self.state[self.position..self.position + len];
+        self.position += len;
+        slice
+    }
+
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        self.slice(N).try_into().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{machine, tests::builder};
+
+    // INR A; STA $2400; OUT 4; JMP $0000
+    const PROGRAM: [u8; 9] = [0x3C, 0x32, 0x00, 0x24, 0xD3, 0x04, 0xC3, 0x00, 0x00];
+
+    fn run(space_invaders: &mut SpaceInvaders, updates: usize) {
+        for _ in 0..updates {
+            space_invaders.update().unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut space_invaders = builder(&PROGRAM).build().unwrap();
+        run(&mut space_invaders, 1000);
+        let mut state = Vec::new();
+        space_invaders.save_state(&mut state).unwrap();
+        assert_eq!(state.len(), LEN + "invaders".len());
+        let (state_hash, cycles) = (space_invaders.state_hash(), space_invaders.cycles());
+
+        run(&mut space_invaders, 10_000);
+        assert_ne!(space_invaders.state_hash(), state_hash);
+        space_invaders.load_state(&state[..]).unwrap();
+        assert_eq!(space_invaders.state_hash(), state_hash);
+        assert_eq!(space_invaders.cycles(), cycles);
+
+        // The loaded machine goes on as the original one did.
+        let mut original = builder(&PROGRAM).build().unwrap();
+        run(&mut original, 1100);
+        run(&mut space_invaders, 100);
+        assert_eq!(space_invaders.state_hash(), original.state_hash());
+    }
+
+    #[test]
+    fn invalid_states() {
+        let mut space_invaders = builder(&PROGRAM).build().unwrap();
+        run(&mut space_invaders, 1000);
+        let mut state = Vec::new();
+        space_invaders.save_state(&mut state).unwrap();
+        run(&mut space_invaders, 1000);
+        let state_hash = space_invaders.state_hash();
+
+        let reason = |state: &[u8], space_invaders: &mut SpaceInvaders| match space_invaders
+            .load_state(state)
+        {
+            Err(Error::InvalidSaveState { reason }) => reason,
+            result => panic!("{result:?}"),
+        };
+        let mut corrupted = state.clone();
+        corrupted[0x2400] ^= 1;
+        assert_eq!(reason(&corrupted, &mut space_invaders), "checksum mismatch");
+        assert_eq!(reason(&state[..state.len() - 1], &mut space_invaders), "truncated");
+        assert_eq!(reason(&state[1..], &mut space_invaders), "not a save state");
+        let mut future = state.clone();
+        future[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(reason(&future, &mut space_invaders), "unsupported version");
+        assert_eq!(space_invaders.state_hash(), state_hash);
+
+        // A state of Space Invaders Part II, which lays out its ROMs and RAM differently.
+        let mut invadpt2 = builder(&PROGRAM)
+            .machine(&machine::INVADPT2)
+            .rom_bytes(vec![0; 0x800])
+            .build()
+            .unwrap();
+        let mut invadpt2_state = Vec::new();
+        invadpt2.save_state(&mut invadpt2_state).unwrap();
+        assert_eq!(reason(&invadpt2_state, &mut space_invaders), "saved by another machine");
+        assert_eq!(reason(&state, &mut invadpt2), "saved by another machine");
+        assert_eq!(space_invaders.state_hash(), state_hash);
+    }
+}
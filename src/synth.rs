@@ -0,0 +1,221 @@
+//! Synthesis of the sounds of Space Invaders, for those whose samples are missing.
+//!
+//! Space Invaders makes its sounds with discrete circuits and an SN76477 sound generator, which
+//! the functions below model loosely: the SN76477 plays the UFO with a voltage-controlled
+//! oscillator swept by its super-low-frequency oscillator, and the player's explosion with its
+//! noise generator; the discrete circuits gate filtered noise with RC one-shots for the shot and
+//! the invader killed, and the four notes of the fleet with a 555 timer. The sounds are rendered
+//! once, as WAV files, so that they are played like samples.
+
+use std::f32::consts::TAU;
+
+use crate::machine::SoundId;
+
+/// The sample rate of the synthesized sounds.
+const SAMPLE_RATE: u32 = 22_050;
+
+/// Returns a WAV file of the synthesized `sound`.
+pub(crate) fn wav(sound: SoundId) -> Vec<u8> {
+    encode(&samples(sound))
+}
+
+fn samples(sound: SoundId) -> Vec<f32> {
+    match sound {
+        // The SN76477 VCO, swept up and down by the SLF, while the UFO is on the screen.
+        SoundId::UfoLowPitch => sweep(1.0 / 6.0, 6.0, 450.0, 800.0, 0.3),
+        // The same, faster and higher, when the UFO is hit.
+        SoundId::UfoHighPitch => fade_out(sweep(1.0, 16.0, 900.0, 1500.0, 0.3), 0.2),
+        // Noise through a low-pass filter, gated by an RC one-shot.
+        SoundId::Shoot => noise(0.5, 5000.0, 0.12, 0.5),
+        SoundId::Explosion => noise(1.5, 1200.0, 0.4, 0.8),
+        SoundId::InvaderKilled => {
+            mix(noise(0.3, 3000.0, 0.06, 0.4), chirp(0.15, 800.0, 150.0, 0.3))
+        }
+        // A 555 timer, gated on and off.
+        SoundId::ExtendedPlay => beeps(1.0, 8.0, 1100.0, 0.3),
+        // A 555 timer through a low-pass filter, with a decaying envelope.
+        SoundId::FastInvader1 => note(65.4),
+        SoundId::FastInvader2 => note(58.3),
+        SoundId::FastInvader3 => note(51.9),
+        SoundId::FastInvader4 => note(49.0),
+    }
+}
+
+fn len(seconds: f32) -> usize {
+    (seconds * SAMPLE_RATE as f32) as usize
+}
+
+/// A square wave whose frequency follows a triangle wave of `rate` Hz between `low` and `high`
+/// Hz.
+fn sweep(seconds: f32, rate: f32, low: f32, high: f32, volume: f32) -> Vec<f32> {
+    let mut phase = 0.0;
+    (0..len(seconds))
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let triangle = 1.0 - (2.0 * (t * rate).fract() - 1.0).abs();
+            phase = (phase + (low + (high - low) * triangle) / SAMPLE_RATE as f32).fract();
+            if phase < 0.5 {
+                volume
+            } else {
+                -volume
+            }
+        })
+        .collect()
+}
+
+/// A square wave whose frequency falls from `from` to `to` Hz, with a decaying envelope.
+fn chirp(seconds: f32, from: f32, to: f32, volume: f32) -> Vec<f32> {
+    let len = len(seconds);
+    let mut phase = 0.0;
+    (0..len)
+        .map(|i| {
+            let progress = i as f32 / len as f32;
+            phase = (phase + (from + (to - from) * progress) / SAMPLE_RATE as f32).fract();
+            let envelope = 1.0 - progress;
+            if phase < 0.5 {
+                volume * envelope
+            } else {
+                -volume * envelope
+            }
+        })
+        .collect()
+}
+
+/// White noise through a low-pass filter with a cutoff of `cutoff` Hz, decaying with a time
+/// constant of `decay` seconds.
+fn noise(seconds: f32, cutoff: f32, decay: f32, volume: f32) -> Vec<f32> {
+    // A 17-bit linear-feedback shift register, as in the noise generator of the SN76477.
+    let mut lfsr = 1_u32;
+    let samples = (0..len(seconds))
+        .map(|i| {
+            lfsr = (lfsr >> 1) | (((lfsr ^ (lfsr >> 3)) & 1) << 16);
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let sample = if lfsr & 1 != 0 { volume } else { -volume };
+            sample * (-t / decay).exp()
+        })
+        .collect();
+    low_pass(samples, cutoff)
+}
+
+/// A tone of `frequency` Hz gated on and off `rate` times per second.
+fn beeps(seconds: f32, rate: f32, frequency: f32, volume: f32) -> Vec<f32> {
+    (0..len(seconds))
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let gate = if (t * rate).fract() < 0.5 { volume } else { 0.0 };
+            if (t * frequency).fract() < 0.5 {
+                gate
+            } else {
+                -gate
+            }
+        })
+        .collect()
+}
+
+/// A note of the fleet, a square wave of `frequency` Hz with a short decay.
+fn note(frequency: f32) -> Vec<f32> {
+    let samples = (0..len(0.15))
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let square = if (t * frequency).fract() < 0.5 { 0.8 } else { -0.8 };
+            square * (-t / 0.05).exp()
+        })
+        .collect();
+    low_pass(samples, 800.0)
+}
+
+/// A one-pole RC low-pass filter with a cutoff of `cutoff` Hz.
+fn low_pass(mut samples: Vec<f32>, cutoff: f32) -> Vec<f32> {
+    let rc = 1.0 / (TAU * cutoff);
+    let dt = 1.0 / SAMPLE_RATE as f32;
+    let alpha = dt / (rc + dt);
+    let mut output = 0.0;
+    for sample in &mut samples {
+        output += alpha * (*sample - output);
+        *sample = output;
+    }
+    samples
+}
+
+/// Fades out the last `seconds` of `samples` linearly.
+fn fade_out(mut samples: Vec<f32>, seconds: f32) -> Vec<f32> {
+    let len = len(seconds).min(samples.len());
+    let start = samples.len() - len;
+    for (i, sample) in samples[start..].iter_mut().enumerate() {
+        *sample *= 1.0 - i as f32 / len as f32;
+    }
+    samples
+}
+
+fn mix(mut samples: Vec<f32>, other: Vec<f32>) -> Vec<f32> {
+    if samples.len() < other.len() {
+        samples.resize(other.len(), 0.0);
+    }
+    for (sample, other) in samples.iter_mut().zip(other) {
+        *sample += other;
+    }
+    samples
+}
+
+/// Encodes `samples` as a WAV file of 16-bit mono PCM.
+fn encode(samples: &[f32]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(&wav_header(SAMPLE_RATE, data_len));
+    for &sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// Returns the header of a WAV file of 16-bit mono PCM at `sample_rate` Hz, whose samples take
+/// `data_len` bytes.
+pub(crate) fn wav_header(sample_rate: u32, data_len: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&36_u32.saturating_add(data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16_u32.to_le_bytes());
+    header.extend_from_slice(&1_u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1_u16.to_le_bytes()); // mono
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    header.extend_from_slice(&2_u16.to_le_bytes()); // bytes per frame
+    header.extend_from_slice(&16_u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rodio::{Decoder, Source};
+
+    use super::*;
+
+    #[test]
+    fn sounds() {
+        for (sound, seconds) in [
+            (SoundId::UfoHighPitch, 1.0),
+            (SoundId::Shoot, 0.5),
+            (SoundId::Explosion, 1.5),
+            (SoundId::InvaderKilled, 0.3),
+            (SoundId::FastInvader1, 0.15),
+            (SoundId::FastInvader2, 0.15),
+            (SoundId::FastInvader3, 0.15),
+            (SoundId::FastInvader4, 0.15),
+            (SoundId::UfoLowPitch, 1.0 / 6.0),
+            (SoundId::ExtendedPlay, 1.0),
+        ] {
+            // The samples are not clipped by the encoding.
+            let samples = samples(sound);
+            assert!(samples.iter().all(|x| (-1.0..=1.0).contains(x)), "{sound:?}");
+            let decoder = Decoder::new(Cursor::new(wav(sound))).unwrap();
+            assert_eq!((decoder.channels(), decoder.sample_rate()), (1, SAMPLE_RATE), "{sound:?}");
+            assert_eq!(decoder.count(), len(seconds), "{sound:?}");
+        }
+    }
+}